@@ -1,3 +1,5 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use movegen::{mv::Move, board::Board};
 pub trait MoveSearch {
     type Input;
@@ -27,12 +29,55 @@ impl<T: MoveSearch> Engine<T> {
     }
 }
 
+mod minimax;
 mod random;
+pub use minimax::Minimax;
 pub use random::Random;
 
 #[derive(Copy, Clone)]
 pub enum AnyEngine {
-    Random
+    Random,
+    Minimax,
+}
+
+impl AnyEngine {
+    pub const ALL: [Self; 2] = [Self::Random, Self::Minimax];
+
+    /// The move this engine picks in `board`, `None` only when the game is over.
+    /// `Random` is seeded from the clock and `Minimax` deepens for as long as its share of `ms_remaining` lasts.
+    pub fn best_move(&self, board: Board, ms_remaining: u64) -> Option<Move> {
+        fn run<T: MoveSearch>(mut engine: Engine<T>, ms_remaining: u64) -> Option<Move> {
+            engine.search(ms_remaining);
+            engine.best_move
+        }
+        match self {
+            Self::Random => {
+                // xorshift never leaves an all zero state, so the seed is kept odd
+                let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64) | 1;
+                run(Engine::<Random>::new(board, seed), ms_remaining)
+            }
+            Self::Minimax => run(Engine::<Minimax>::new(board, u8::MAX), ms_remaining),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use movegen::board::Board;
+
+    use crate::AnyEngine;
+
+    #[test]
+    fn any_engine() {
+        let mut board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+            .expect("fen is valid");
+        let mated = Board::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").expect("fen is valid");
+        for engine in AnyEngine::ALL {
+            let mv = engine.best_move(board, 300).expect("position has legal moves");
+            assert!(board.get_moves().contains(&mv), "{}", mv.to_string());
+            assert_eq!(engine.best_move(mated, 300), None);
+        }
+    }
 }
 
 
@@ -0,0 +1,342 @@
+use std::time::{Duration, Instant};
+
+use movegen::{
+    board::{Board, DrawReason, Status},
+    history::History,
+    move_list::MoveList,
+    mv::{Move, MoveFlag},
+    see::SEE_VALUES,
+    Color, Piece,
+};
+
+use crate::MoveSearch;
+
+/// Score for being checkmated at the root, a mate `n` plies away scores `n` closer to zero
+const MATE: i32 = 1_000_000;
+/// The clock is read once every this many nodes
+const NODES_PER_TIME_CHECK: u64 = 1024;
+/// A move may use this fraction of the remaining clock, so it lasts for the rest of the game
+const MOVES_TO_GO: u64 = 30;
+
+fn value(piece: &Piece) -> i32 {
+    SEE_VALUES[*piece as usize]
+}
+
+/// Material of the side to move minus that of the opponent
+fn evaluate(board: &Board) -> i32 {
+    let color = &board.state.active_color;
+    [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen]
+        .iter()
+        .map(|piece| {
+            let count = |color: &Color| board.pieces[color][piece].sq_count() as i32;
+            value(piece) * (count(color) - count(!color))
+        })
+        .sum()
+}
+
+/// Captures of the most valuable victim by the least valuable attacker first, then promotions, then quiet moves
+fn order_score(mv: &Move) -> i32 {
+    let victim = match mv.flag {
+        MoveFlag::Capture(piece) => value(&piece),
+        MoveFlag::EnPassant => value(&Piece::Pawn),
+        _ => 0,
+    };
+    let promotion = mv.promotion.map_or(0, |promotion| value(&promotion.piece()));
+    if victim + promotion == 0 {
+        0
+    } else {
+        10 * (victim + promotion) - value(&mv.piece) / 100
+    }
+}
+
+/// Negamax alpha-beta search with iterative deepening. Every iteration searches the best move of the previous one
+/// first, so the moves of an iteration cut short by the clock can still be used.
+#[derive(Debug, Clone)]
+pub struct Minimax {
+    max_depth: u8,
+    deadline: Instant,
+    nodes: u64,
+    aborted: bool,
+    /// Positions from the root to the current node, for repetition draws
+    history: History,
+}
+
+impl Minimax {
+    fn out_of_time(&mut self) -> bool {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(NODES_PER_TIME_CHECK) && Instant::now() >= self.deadline {
+            self.aborted = true;
+        }
+        self.aborted
+    }
+
+    fn negamax(&mut self, board: &mut Board, depth: u8, ply: i32, mut alpha: i32, beta: i32) -> i32 {
+        if self.out_of_time() {
+            return 0;
+        }
+        // The draw rules don't need the legal moves, only the fifty move rule has to wait for them in check
+        // since checkmate comes first. Quiet leaves go straight to quiescence, which generates captures only.
+        let in_check = board.in_check();
+        match board.draw_reason(&self.history) {
+            Some(DrawReason::FiftyMoveRule) if in_check => {}
+            Some(_) => return 0,
+            None => {}
+        }
+        if depth == 0 && !in_check {
+            return self.quiescence(board, ply, alpha, beta);
+        }
+        let mut moves = board.get_moves();
+        if moves.is_empty() {
+            return if in_check { -(MATE - ply) } else { 0 };
+        }
+        if board.state.half_move_count >= 100 {
+            return 0;
+        }
+        if depth == 0 {
+            return self.quiescence(board, ply, alpha, beta);
+        }
+
+        moves.sort_by_score(order_score);
+        let mut best = -MATE;
+        for mv in moves.iter() {
+            let undo = board.make_move(mv);
+            self.history.push(board.zobrist);
+            let score = -self.negamax(board, depth - 1, ply + 1, -beta, -alpha);
+            self.history.pop();
+            board.unmake_move(mv, undo);
+            if self.aborted {
+                return 0;
+            }
+            best = best.max(score);
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+
+    /// Searches captures that do not lose material until the position is quiet, the side to move can always
+    /// stand pat on the static evaluation instead. In check there is no standing pat, every evasion is searched.
+    fn quiescence(&mut self, board: &mut Board, ply: i32, mut alpha: i32, beta: i32) -> i32 {
+        let mut moves = MoveList::new();
+        let mut best = -MATE;
+        if board.in_check() {
+            board.generate_evasions(|_, mv| moves.push(*mv));
+            if moves.is_empty() {
+                return -(MATE - ply);
+            }
+        } else {
+            let stand_pat = evaluate(board);
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+            best = stand_pat;
+            board.generate_captures(|board, mv| {
+                if board.see_ge(mv, 0) {
+                    moves.push(*mv)
+                }
+            });
+        }
+        moves.sort_by_score(order_score);
+        for mv in moves.iter() {
+            if self.out_of_time() {
+                return 0;
+            }
+            let undo = board.make_move(mv);
+            let score = -self.quiescence(board, ply + 1, -beta, -alpha);
+            board.unmake_move(mv, undo);
+            best = best.max(score);
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+}
+
+impl MoveSearch for Minimax {
+    /// The deepest iteration to search
+    type Input = u8;
+
+    fn init(max_depth: Self::Input) -> Self {
+        Self {
+            max_depth,
+            deadline: Instant::now(),
+            nodes: 0,
+            aborted: false,
+            history: History::default(),
+        }
+    }
+
+    /// Deepens until `max_depth`, a forced mate is found or `ms_remaining / MOVES_TO_GO` runs out.
+    /// Leaves `best_move` empty only when the game is over.
+    fn search(&mut self, board: &mut Board, best_move: &mut Option<Move>, ms_remaining: u64) {
+        self.deadline = Instant::now() + Duration::from_millis(ms_remaining / MOVES_TO_GO);
+        self.nodes = 0;
+        self.aborted = false;
+        self.history = History::new(board);
+
+        let Status::Ongoing(mut moves) = board.status(&self.history) else {
+            return;
+        };
+        moves.sort_by_score(order_score);
+        *best_move = Some(moves[0]);
+
+        for depth in 1..=self.max_depth {
+            if let Some(i) = moves.iter().position(|mv| Some(*mv) == *best_move) {
+                moves[..=i].rotate_right(1);
+            }
+            let mut alpha = -MATE;
+            for mv in moves.iter() {
+                let undo = board.make_move(mv);
+                self.history.push(board.zobrist);
+                let score = -self.negamax(board, depth - 1, 1, -MATE, -alpha);
+                self.history.pop();
+                board.unmake_move(mv, undo);
+                if self.aborted {
+                    break;
+                }
+                if score > alpha {
+                    alpha = score;
+                    *best_move = Some(*mv);
+                }
+            }
+            if self.aborted || alpha >= MATE - depth as i32 {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use movegen::{
+        board::{Board, Status},
+        history::History,
+    };
+
+    use crate::{minimax::MATE, Engine, Minimax, MoveSearch};
+
+    /// Whether the side to move can force checkmate within `moves` of its own moves
+    fn mates_in(board: &mut Board, moves: u8) -> bool {
+        let Status::Ongoing(own_moves) = board.status(&History::new(board)) else {
+            return false;
+        };
+        if moves == 0 {
+            return false;
+        }
+        own_moves.iter().any(|mv| {
+            let undo = board.make_move(mv);
+            let mates = match board.status(&History::new(board)) {
+                Status::Checkmate => true,
+                Status::Ongoing(replies) if moves > 1 => replies.iter().all(|reply| {
+                    let undo = board.make_move(reply);
+                    let mates = mates_in(board, moves - 1);
+                    board.unmake_move(reply, undo);
+                    mates
+                }),
+                _ => false,
+            };
+            board.unmake_move(mv, undo);
+            mates
+        })
+    }
+
+    fn search(fen: &str, max_depth: u8, ms_remaining: u64) -> Engine<Minimax> {
+        let mut engine = Engine::<Minimax>::new(Board::from_fen(fen).expect("fen is valid"), max_depth);
+        engine.search(ms_remaining);
+        engine
+    }
+
+    #[test]
+    fn finds_mate() {
+        for (fen, moves) in [
+            ("6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1", 1),
+            ("r5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1", 1),
+            ("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 4 4", 1),
+            ("k7/8/2K5/8/8/8/8/7R w - - 0 1", 2),
+            ("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 2),
+        ] {
+            let mut engine = search(fen, 6, 60_000);
+            let mv = engine.best_move.expect("position has legal moves");
+            assert!(!mates_in(&mut engine.board, moves - 1), "{fen} is not a mate in {moves}");
+            engine.board.make_move(&mv);
+            let mated = match engine.board.status(&History::new(&engine.board)) {
+                Status::Checkmate => moves == 1,
+                Status::Ongoing(replies) => replies.iter().all(|reply| {
+                    let mut board = engine.board;
+                    board.make_move(reply);
+                    mates_in(&mut board, moves - 1)
+                }),
+                _ => false,
+            };
+            assert!(mated, "{fen} {}", mv.to_string());
+        }
+    }
+
+    #[test]
+    fn legal_moves() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "4k3/8/8/8/8/8/4q3/4K3 w - - 0 1",
+        ] {
+            for (max_depth, ms_remaining) in [(1, 1000), (3, 1000), (u8::MAX, 0)] {
+                let mut engine = search(fen, max_depth, ms_remaining);
+                let mv = engine.best_move.expect("position has legal moves");
+                assert!(engine.board.get_moves().contains(&mv), "{fen} {}", mv.to_string());
+            }
+        }
+        // checkmate and stalemate
+        for fen in ["R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", "k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"] {
+            assert_eq!(search(fen, 4, 1000).best_move, None, "{fen}");
+        }
+    }
+
+    #[test]
+    fn quiescence_in_check() {
+        // standing pat would score the checkmate as a rook down
+        let mut board = Board::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").expect("fen is valid");
+        assert_eq!(Minimax::init(1).quiescence(&mut board, 3, -MATE, MATE), -(MATE - 3));
+        // a queen up by the static evaluation, but every king move lets the knight take the queen
+        let mut board = Board::from_fen("3q3k/5N2/8/8/8/8/8/K7 b - - 0 1").expect("fen is valid");
+        assert!(Minimax::init(1).quiescence(&mut board, 0, -MATE, MATE) < 0);
+    }
+
+    #[test]
+    fn fifty_move_rule() {
+        // checkmate on the hundredth half move still counts, at a leaf as well
+        let mut board = Board::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 80").expect("fen is valid");
+        for depth in [0, 2] {
+            assert_eq!(Minimax::init(depth).negamax(&mut board, depth, 3, -MATE, MATE), -(MATE - 3));
+        }
+        // otherwise a rook up is a draw
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 100 80").expect("fen is valid");
+        for depth in [0, 2] {
+            assert_eq!(Minimax::init(depth).negamax(&mut board, depth, 0, -MATE, MATE), 0);
+        }
+    }
+
+    #[test]
+    fn obeys_time() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut board = Board::from_fen(fen).expect("fen is valid");
+        let mut minimax = Minimax::init(u8::MAX);
+        let mut best_move = None;
+        let start = Instant::now();
+        minimax.search(&mut board, &mut best_move, 3000);
+        assert!(best_move.is_some());
+        // without a depth limit only the clock can end the search
+        assert!(minimax.aborted);
+        assert!(minimax.deadline <= Instant::now());
+        // a single move gets 100ms of the clock, which is read every `NODES_PER_TIME_CHECK` nodes
+        assert!(start.elapsed() < Duration::from_millis(1000), "took {:?}", start.elapsed());
+    }
+}
@@ -1,6 +1,9 @@
 use std::{thread, time::Duration};
 
-use movegen::board::{Board, Status};
+use movegen::{
+    board::{Board, Status},
+    history::History,
+};
 
 use crate::{Engine, MoveSearch};
 
@@ -33,7 +36,7 @@ impl MoveSearch for Random {
         }
     }
     fn search(&mut self, board: &mut Board, best_move: &mut Option<movegen::mv::Move>, ms_remaining: u64) {
-        match board.status() {
+        match board.status(&History::new(board)) {
             Status::Ongoing(moves) => {
                 let idx = self.rand.next() as usize % moves.len();
                 *best_move = Some(moves[idx]);
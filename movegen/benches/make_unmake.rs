@@ -0,0 +1,61 @@
+//! Compares copy-make against make/unmake for walking the move tree.
+//! Run with `cargo bench -p movegen --bench make_unmake`.
+
+use std::time::{Duration, Instant};
+
+use movegen::{board::Board, mv::Move};
+
+fn perft_copy_make(board: &mut Board, depth: u8) -> u64 {
+    let mut total = 0;
+    board.generate_moves(|board: &mut Board, mv: &Move| {
+        if depth == 1 {
+            total += 1;
+        } else {
+            let mut new_board = *board;
+            new_board.make_move(mv);
+            total += perft_copy_make(&mut new_board, depth - 1);
+        }
+    });
+    total
+}
+
+fn perft_make_unmake(board: &mut Board, depth: u8) -> u64 {
+    let mut total = 0;
+    board.generate_moves(|board: &mut Board, mv: &Move| {
+        if depth == 1 {
+            total += 1;
+        } else {
+            let undo = board.make_move(mv);
+            total += perft_make_unmake(board, depth - 1);
+            board.unmake_move(mv, undo);
+        }
+    });
+    total
+}
+
+fn time<F: FnMut() -> u64>(mut f: F) -> (u64, Duration) {
+    let start = Instant::now();
+    let nodes = f();
+    (nodes, start.elapsed())
+}
+
+fn main() {
+    const POSITIONS: &[(&str, &str, u8)] = &[
+        ("startpos", "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 6),
+        ("kiwipete", "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 5),
+        ("position_3", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 6),
+    ];
+
+    for (name, fen, depth) in POSITIONS {
+        let board = Board::from_fen(fen).expect("bench fen is valid");
+
+        let (copy_nodes, copy_time) = time(|| perft_copy_make(&mut board.clone(), *depth));
+        let (unmake_nodes, unmake_time) = time(|| perft_make_unmake(&mut board.clone(), *depth));
+        assert_eq!(copy_nodes, unmake_nodes, "strategies disagree on {name}");
+
+        let mnps = |t: Duration| 10f64.powf(-6.0) * copy_nodes as f64 / t.as_secs_f64();
+        println!("{name} depth {depth} ({copy_nodes} nodes)");
+        println!("  copy-make:   {:>8.3}s {:>8.2} million moves/sec", copy_time.as_secs_f64(), mnps(copy_time));
+        println!("  make/unmake: {:>8.3}s {:>8.2} million moves/sec", unmake_time.as_secs_f64(), mnps(unmake_time));
+    }
+}
@@ -0,0 +1,35 @@
+//! Measures how `perft_multithread` scales with the number of threads.
+//! Run with `cargo bench -p movegen --bench perft_scaling`.
+
+use std::time::Instant;
+
+use movegen::board::Board;
+
+fn main() {
+    const POSITIONS: &[(&str, &str, u8)] = &[
+        ("startpos", "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 6),
+        ("kiwipete", "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 5),
+        ("position_3", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 7),
+        ("position_4", "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 5),
+        ("position_5", "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 5),
+    ];
+    let max_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let thread_counts = (0..).map(|i| 1 << i).take_while(|n| *n <= max_threads);
+
+    for (name, fen, depth) in POSITIONS {
+        let board = Board::from_fen(fen).expect("bench fen is valid");
+        println!("{name} depth {depth}");
+
+        let mut single_thread_secs = None;
+        for thread_count in thread_counts.clone() {
+            let start = Instant::now();
+            let nodes = board.clone().perft_multithread(*depth, thread_count);
+            let secs = start.elapsed().as_secs_f64();
+            let speedup = *single_thread_secs.get_or_insert(secs) / secs;
+            println!(
+                "  {thread_count:>3} threads: {secs:>8.3}s {:>8.2} million moves/sec {speedup:>5.2}x ({nodes} nodes)",
+                10f64.powf(-6.0) * nodes as f64 / secs
+            );
+        }
+    }
+}
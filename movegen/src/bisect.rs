@@ -0,0 +1,194 @@
+use util::error::ChessError;
+
+use crate::board::Board;
+
+/// Known-good divide counts to bisect against. Moves are written like `Board::move_to_string`.
+pub trait DivideReference {
+    /// The perft count below each legal move of `board`, or `None` if the reference does not know the position
+    fn divide(&mut self, board: &Board, depth: u8) -> Option<Vec<(String, u64)>>;
+}
+
+/// Divide output read from a file, one `<move>: <count>` per line like `perft` prints it.
+/// The lines before the first header are for the root position, later sections start with a header
+/// like `<fen> ;D<depth>` so the divide of positions further down the tree can be added as bisecting asks for them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DivideFile {
+    root: Vec<(String, u64)>,
+    sections: Vec<Section>,
+}
+
+/// The divide below a header
+#[derive(Debug, Clone, PartialEq)]
+struct Section {
+    zobrist: u64,
+    depth: u8,
+    divide: Vec<(String, u64)>,
+}
+
+impl DivideFile {
+    /// Skips empty lines, `#` comments and the totals printed after a divide
+    pub fn parse(input: &str) -> Result<Self, ChessError> {
+        let mut file = Self { root: Vec::new(), sections: Vec::new() };
+        for (i, line) in input.lines().enumerate() {
+            let line = line.trim();
+            let lowercase = line.to_lowercase();
+            if line.is_empty()
+                || line.starts_with('#')
+                || ["total", "nodes searched", "million moves/sec"].iter().any(|s| lowercase.starts_with(s))
+            {
+                continue;
+            }
+            let err = |msg: String| ChessError::Parse(format!("line {}: {msg}", i + 1));
+
+            if let Some((fen, depth)) = line.split_once(';') {
+                let board = Board::from_fen(fen.trim()).map_err(|e| err(format!("{e:?}")))?;
+                let depth = depth
+                    .trim()
+                    .strip_prefix('D')
+                    .and_then(|d| d.parse().ok())
+                    .ok_or_else(|| err(format!("'{depth}' is not a depth like 'D3'")))?;
+                file.sections.push(Section { zobrist: board.zobrist, depth, divide: Vec::new() });
+                continue;
+            }
+
+            let (mv, count) = line
+                .split_once(':')
+                .and_then(|(mv, count)| Some((mv.trim().to_string(), count.trim().parse().ok()?)))
+                .ok_or_else(|| err(format!("'{line}' is neither a header nor a line like 'e2e4: 20'")))?;
+            match file.sections.last_mut() {
+                Some(section) => section.divide.push((mv, count)),
+                None => file.root.push((mv, count)),
+            }
+        }
+        Ok(file)
+    }
+}
+
+impl DivideReference for DivideFile {
+    /// The root divide is used for the first position asked for, whatever its depth
+    fn divide(&mut self, board: &Board, depth: u8) -> Option<Vec<(String, u64)>> {
+        if !self.root.is_empty() {
+            return Some(std::mem::take(&mut self.root));
+        }
+        self.sections
+            .iter()
+            .find(|section| section.zobrist == board.zobrist && section.depth == depth)
+            .map(|section| section.divide.clone())
+    }
+}
+
+/// How the generator first disagrees with the reference
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mismatch {
+    /// Generated, but not in the reference
+    Extra(String),
+    /// In the reference, but not generated
+    Missing(String),
+    /// Both have the move with different counts at depth 1, so the reference must be wrong
+    Count { mv: String, expected: u64, found: u64 },
+    /// The reference has no divide for the position, bisecting can go on once it has
+    NoReference,
+}
+
+/// The position where bisecting stopped
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bisection {
+    /// Moves leading from the root to `fen`
+    pub path: Vec<String>,
+    pub fen: String,
+    pub depth: u8,
+    pub mismatch: Mismatch,
+}
+
+impl Board {
+    /// Compares `divide(depth)` against the reference and follows the first move whose count differs
+    /// until the counts disagree on a single move. Returns `None` if the counts at the root match.
+    pub fn bisect(&self, depth: u8, reference: &mut impl DivideReference) -> Option<Bisection> {
+        let mut board = *self;
+        let mut path = Vec::new();
+        let mut depth = depth;
+        loop {
+            let stop = |board: &Board, path: Vec<String>, mismatch| {
+                Some(Bisection { path, fen: board.fen(false), depth, mismatch })
+            };
+            let Some(expected) = reference.divide(&board, depth) else {
+                return stop(&board, path, Mismatch::NoReference);
+            };
+            let found = board.divide(depth, None);
+            let found_strs: Vec<_> = found.iter().map(|(mv, count)| (board.move_to_string(mv), *count)).collect();
+
+            if let Some((mv, _)) = expected.iter().find(|(mv, _)| found_strs.iter().all(|(m, _)| m != mv)) {
+                return stop(&board, path, Mismatch::Missing(mv.clone()));
+            }
+            if let Some((mv, _)) = found_strs.iter().find(|(mv, _)| expected.iter().all(|(m, _)| m != mv)) {
+                return stop(&board, path, Mismatch::Extra(mv.clone()));
+            }
+            let (i, expected_count) = found_strs.iter().enumerate().find_map(|(i, (mv, count))| {
+                let (_, expected_count) = expected.iter().find(|(m, _)| m == mv)?;
+                (expected_count != count).then_some((i, *expected_count))
+            })?;
+
+            let (mv, count) = &found[i];
+            let mv_str = found_strs[i].0.clone();
+            if depth == 1 {
+                return stop(&board, path, Mismatch::Count { mv: mv_str, expected: expected_count, found: *count });
+            }
+            board.make_move(mv);
+            path.push(mv_str);
+            depth -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        bisect::{Bisection, DivideFile, Mismatch},
+        board::Board,
+        mv::Move,
+    };
+
+    /// Divide output for the position after `moves`, with the counts changed by `edit`
+    fn divide_section(moves: &[&str], depth: u8, edit: impl Fn(&str, u64) -> Option<u64>) -> String {
+        let mut board = Board::default();
+        for mv in moves {
+            let mv = Move::from_str(mv, &mut board).expect("move is legal");
+            board.make_move(&mv);
+        }
+        let mut section = format!("{} ;D{depth}\n", board.fen(false));
+        for (mv, count) in board.divide(depth, None) {
+            if let Some(count) = edit(&mv.to_string(), count) {
+                section += &format!("{}: {count}\n", mv.to_string());
+            }
+        }
+        section
+    }
+
+    #[test]
+    fn bisect() {
+        let board = Board::default();
+        let mut reference = DivideFile::parse(&divide_section(&[], 3, |_, count| Some(count))).expect("divide parses");
+        assert_eq!(board.bisect(3, &mut reference), None);
+
+        // The reference is missing the reply a2a3 to e2e4 a7a6
+        let root = divide_section(&[], 3, |mv, count| Some(count - (mv == "e2e4") as u64));
+        let mut file = root.lines().skip(1).collect::<Vec<_>>().join("\n")
+            + "\nTotal: 8901\n"
+            + &divide_section(&["e2e4"], 2, |mv, count| Some(count - (mv == "a7a6") as u64));
+        let mut reference = DivideFile::parse(&file).expect("divide parses");
+        let fen = "rnbqkbnr/1ppppppp/p7/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2".to_string();
+        let path = vec!["e2e4".to_string(), "a7a6".to_string()];
+        assert_eq!(
+            board.bisect(3, &mut reference),
+            Some(Bisection { path: path.clone(), fen: fen.clone(), depth: 1, mismatch: Mismatch::NoReference })
+        );
+
+        file += "# after e2e4 a7a6\n";
+        file += &divide_section(&["e2e4", "a7a6"], 1, |mv, count| (mv != "a2a3").then_some(count));
+        let mut reference = DivideFile::parse(&file).expect("divide parses");
+        assert_eq!(
+            board.bisect(3, &mut reference),
+            Some(Bisection { path, fen, depth: 1, mismatch: Mismatch::Extra("a2a3".to_string()) })
+        );
+    }
+}
@@ -2,9 +2,12 @@ use std::{thread, time::Duration};
 
 use crate::{
     both_colors::BothColors,
+    history::History,
+    move_list::MoveList,
     mv::{Move, MoveFlag, Promotion},
     piece_bb::PieceBitboards,
-    state::State,
+    state::{Castling, State},
+    zobrist,
     movegen::{
         pawn_attacks,
         knight_moves,
@@ -25,12 +28,19 @@ use util::{
     square::{File, Square, Rank},
 };
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Board {
     pub state: State,
     pub pieces: BothColors<PieceBitboards>,
     pub pinned: Bitboard,
     pub check_masks: [Option<(Square, Bitboard, Option<Bitboard>)>; 2],
+    pub zobrist: u64,
+    /// The piece on every square, kept in sync with `pieces` by `toggle_sq`
+    pub(crate) mailbox: [Option<(Color, Piece)>; 64],
+    /// Squares attacked by the pawns, knights and king of each color, updated by `make_move` and `unmake_move`
+    pub(crate) leaper_attacks: BothColors<Bitboard>,
+    /// Squares attacked by the bishops, rooks and queens of each color, updated by `make_move` and `unmake_move`
+    pub(crate) slider_attacks: BothColors<Bitboard>,
 }
 
 impl Default for Board {
@@ -40,17 +50,88 @@ impl Default for Board {
     }
 }
 
+/// Everything `make_move` overwrites that cannot be derived from the move itself.
+/// Returned by `make_move` and consumed by `unmake_move`.
+#[derive(Debug, Clone, Copy)]
+pub struct Undo {
+    state: State,
+    pinned: Bitboard,
+    check_masks: [Option<(Square, Bitboard, Option<Bitboard>)>; 2],
+    zobrist: u64,
+    leaper_attacks: BothColors<Bitboard>,
+    slider_attacks: BothColors<Bitboard>,
+}
+
+// Boxing the move list would bring back the allocation MoveList exists to avoid
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum Status {
     Checkmate,
     Stalemate,
-    Draw,
-    Ongoing(Vec<Move>)
+    Draw(DrawReason),
+    Ongoing(MoveList)
+}
+
+/// Which subset of the legal moves to generate.
+/// Promotions count as captures, so captures and quiets never overlap and together make up all legal moves.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MoveGenMode {
+    All,
+    Captures,
+    Quiets,
+    /// Only generated in check, every move has to land in the check mask
+    Evasions,
+}
+
+impl MoveGenMode {
+    /// The squares moves are restricted to by `check_mask`, which is `None` when not in check
+    fn check_mask(&self, check_mask: Option<Bitboard>) -> Bitboard {
+        match self {
+            Self::Evasions => check_mask.expect("evasions are only generated in check"),
+            _ => check_mask.unwrap_or(Bitboard::FULL),
+        }
+    }
+
+    fn targets(&self, opp_all: Bitboard, check_mask: Option<Bitboard>) -> Bitboard {
+        let targets = match self {
+            Self::All | Self::Evasions => Bitboard::FULL,
+            Self::Captures => opp_all,
+            Self::Quiets => !opp_all,
+        };
+        targets & self.check_mask(check_mask)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DrawReason {
+    FiftyMoveRule,
+    ThreefoldRepetition,
+    InsufficientMaterial,
 }
 
 
 impl Board {
     pub const WIDTH: u8 = 7;
+
+    /// Sets up a board from its pieces and state, computing everything else from scratch
+    pub(crate) fn from_parts(pieces: BothColors<PieceBitboards>, state: State) -> Self {
+        let mut board = Self {
+            pinned: Bitboard::EMPTY,
+            check_masks: [None, None],
+            zobrist: 0,
+            mailbox: [None; 64],
+            leaper_attacks: BothColors::default(),
+            slider_attacks: BothColors::default(),
+            pieces,
+            state,
+        };
+
+        board.update_checks_pins();
+        board.update_mailbox();
+        board.update_attacks(&BothColors::default());
+        board.zobrist = board.compute_zobrist();
+        board
+    }
     
     pub fn in_check(&self) -> bool {
         match self.check_masks {
@@ -59,18 +140,103 @@ impl Board {
         }
     }
     
-    pub fn status(&mut self) -> Status {
+    /// Whether making `mv` would put the opponent in check, without making it.
+    /// Covers direct checks, discovered checks, checks by the promoted piece,
+    /// checks discovered by the pawn captured en passant and checks by the castling rook.
+    pub fn gives_check(&self, mv: &Move) -> bool {
+        let active_color = &self.state.active_color;
+        let opp_color = !active_color;
+        let Some(opp_king_sq) = self.pieces[opp_color].king.clone().next_sq() else {
+            return false;
+        };
+        let pieces = &self.pieces[active_color];
+        let to_piece = mv.promotion.map_or(mv.piece, |p| p.piece());
+
+        let mut occupancy = (pieces.all | self.pieces[opp_color].all) ^ mv.from.bitboard();
+        let mut diagonal = (pieces.bishop | pieces.queen) & !mv.from.bitboard();
+        let mut straight = (pieces.rook | pieces.queen) & !mv.from.bitboard();
+        match mv.flag {
+            MoveFlag::EnPassant => occupancy ^= Square::ep_pawn_sq(opp_color, mv.to.file()).bitboard(),
+            MoveFlag::KingSideCastles | MoveFlag::QueenSideCastles => {
+                let rook_sq = self.castling_rook_sq(mv).expect("castling requires castling rights");
+                let rook_dest_file = if mv.flag == MoveFlag::KingSideCastles { File::F } else { File::D };
+                let rook_dest_sq = Square::from_rank_file(rook_sq.rank(), rook_dest_file);
+                occupancy = (occupancy & !rook_sq.bitboard()) | rook_dest_sq.bitboard();
+                straight = (straight & !rook_sq.bitboard()) | rook_dest_sq.bitboard();
+            }
+            _ => {}
+        }
+        occupancy |= mv.to.bitboard();
+        match to_piece {
+            Piece::Bishop => diagonal |= mv.to.bitboard(),
+            Piece::Rook => straight |= mv.to.bitboard(),
+            Piece::Queen => {
+                diagonal |= mv.to.bitboard();
+                straight |= mv.to.bitboard();
+            }
+            _ => {}
+        }
+
+        let direct = match to_piece {
+            Piece::Pawn => pawn_attacks(*active_color, &mv.to),
+            Piece::Knight => knight_moves(&mv.to),
+            _ => Bitboard::EMPTY,
+        };
+        direct.has_sq(opp_king_sq)
+            || !(bishop_moves(&opp_king_sq, occupancy) & diagonal).is_empty()
+            || !(rook_moves(&opp_king_sq, occupancy) & straight).is_empty()
+    }
+
+    /// `history` has to end with this position for threefold repetition to be detected
+    pub fn status(&mut self, history: &History) -> Status {
         let moves = self.get_moves();
-        if moves.len() == 0 {
+        if moves.is_empty() {
             if self.in_check() {
                 Status::Checkmate
             } else {
                 Status::Stalemate
             }
+        } else if let Some(reason) = self.draw_reason(history) {
+            Status::Draw(reason)
         } else {
             Status::Ongoing(moves)
         }
     }
+
+    /// Checks the draw rules that don't depend on the legal moves of the position.
+    /// Stalemate is reported separately by `status`.
+    pub fn draw_reason(&self, history: &History) -> Option<DrawReason> {
+        if self.state.half_move_count >= 100 {
+            Some(DrawReason::FiftyMoveRule)
+        } else if history.repetitions(self.state.half_move_count) >= 3 {
+            Some(DrawReason::ThreefoldRepetition)
+        } else if self.has_insufficient_material() {
+            Some(DrawReason::InsufficientMaterial)
+        } else {
+            None
+        }
+    }
+
+    /// True for KvK, KNvK, KBvK and any number of bishops that are all on the same square color
+    pub fn has_insufficient_material(&self) -> bool {
+        let w = &self.pieces[&Color::White];
+        let b = &self.pieces[&Color::Black];
+
+        if !(w.pawn | b.pawn | w.rook | b.rook | w.queen | b.queen).is_empty() {
+            return false;
+        }
+
+        let knights = w.knight | b.knight;
+        let bishops = w.bishop | b.bishop;
+
+        if (knights | bishops).sq_count() <= 1 {
+            return true;
+        }
+
+        knights.is_empty()
+            && ((bishops & Bitboard::LIGHT_SQUARES).is_empty()
+                || (bishops & Bitboard::DARK_SQUARES).is_empty())
+    }
     
     pub fn all_sqs(&self, white_pov: bool) -> Vec<Vec<(Square, Option<(Color, Piece)>)>> {
         let mut output = Vec::with_capacity(8);
@@ -81,7 +247,7 @@ impl Board {
             output.push(Vec::with_capacity(8));
             File::ALL.iter().for_each(|file|{
                 let sq = Square::from_rank_file(*rank, *file);
-                output.last_mut().expect("always has row").push((sq, self.get_sq(sq)))
+                output.last_mut().expect("always has row").push((sq, self.piece_at(sq)))
             })
         };
         
@@ -94,24 +260,12 @@ impl Board {
         output
     }
 
-    fn get_sq(&self, sq: Square) -> Option<(Color, Piece)> {
-        for piece in &Piece::ALL {
-            let w = self.pieces[&Color::White][piece];
-            let b = self.pieces[&Color::Black][piece];
-
-            if w.has_sq(sq) {
-                return Some((Color::White, *piece));
-            }
-
-            if b.has_sq(sq) {
-                return Some((Color::Black, *piece));
-            }
-        }
-        None
+    pub fn piece_at(&self, sq: Square) -> Option<(Color, Piece)> {
+        self.mailbox[sq.idx()]
     }
 
-    pub fn get_sq_moves(&mut self, sq: Square) -> Vec<Move> {
-        let mut moves = Vec::new();
+    pub fn get_sq_moves(&mut self, sq: Square) -> MoveList {
+        let mut moves = MoveList::new();
         let callback = |_: &mut Self, mv: &Move| {
             if mv.from == sq {
                 moves.push(*mv)
@@ -121,8 +275,8 @@ impl Board {
         moves
     }
 
-    pub fn get_moves(&mut self) -> Vec<Move> {
-        let mut moves = Vec::new();
+    pub fn get_moves(&mut self) -> MoveList {
+        let mut moves = MoveList::new();
         let callback = |_: &mut Self, mv: &Move| {
             moves.push(*mv);
         };
@@ -134,57 +288,101 @@ impl Board {
     where
         T: FnMut(&mut Self, &Move),
     {
+        if self.in_check() {
+            return;
+        }
 
-        
-        let blockers = self.pieces[color].all | self.pieces[!color].all;
-        let mut move_caller = |board: &mut Self,
-                               is_kingside: bool,
-                               rook_file: File,
-                               king_dest_file: File,
-                               rook_dest_file: File| {
-            let king_sq = Square::from_rank_file(Rank::First.pov(color), File::E);
-            let rook_sq = Square::from_rank_file(Rank::First.pov(color), rook_file);
-            let rook_dest_sq = Square::from_rank_file(Rank::First.pov(color), rook_dest_file);
-            let king_dest_sq = Square::from_rank_file(Rank::First.pov(color), king_dest_file);
-
-            let sqs_between = sq_between(&king_sq, &rook_sq);
-            let a1 = board.sq_is_attacked_by(&rook_dest_sq, !color);
-            let a2 = board.sq_is_attacked_by(&king_dest_sq, !color);
-
-            if (blockers & sqs_between).is_empty() && !a1 && !a2 {
-                callback(
-                    board,
-                    &Move {
-                        piece: Piece::King,
-                        from: king_sq,
-                        to: king_dest_sq,
-                        flag: if is_kingside {
-                            MoveFlag::KingSideCastles
-                        } else {
-                            MoveFlag::QueenSideCastles
-                        },
-                        promotion: None
-                    },
-                )
+        let castling = self.state.castling[color];
+        let rank = Rank::First.pov(color);
+        let king_sq = Square::from_rank_file(rank, castling.king_file);
+        if !self.pieces[color].king.has_sq(king_sq) {
+            return;
+        }
+        let all = self.pieces[color].all | self.pieces[!color].all;
+
+        for (rook_file, king_dest_file, rook_dest_file, flag) in [
+            (castling.king_side, File::G, File::F, MoveFlag::KingSideCastles),
+            (castling.queen_side, File::C, File::D, MoveFlag::QueenSideCastles),
+        ] {
+            let Some(rook_file) = rook_file else {
+                continue;
+            };
+            let rook_sq = Square::from_rank_file(rank, rook_file);
+            if !self.pieces[color].rook.has_sq(rook_sq) {
+                continue;
             }
-        };
-        match self.check_masks {
-            [None, None] => {
-                if self.state.castling[color].king_side {
-                    move_caller(self, true, File::H, File::G, File::F);
-                }
-                if self.state.castling[color].queen_side {
-                    move_caller(self, false, File::A, File::C, File::D);
-                }
+            let king_dest_sq = Square::from_rank_file(rank, king_dest_file);
+            let rook_dest_sq = Square::from_rank_file(rank, rook_dest_file);
+
+            // In chess960 the king and rook may already stand on or next to their destinations,
+            // so only the two castling pieces themselves may be in the way
+            let king_path = sq_between(&king_sq, &king_dest_sq) | king_dest_sq.bitboard();
+            let rook_path = sq_between(&rook_sq, &rook_dest_sq) | rook_dest_sq.bitboard();
+            let blockers = all & !(king_sq.bitboard() | rook_sq.bitboard());
+            if !((king_path | rook_path) & blockers).is_empty() {
+                continue;
             }
-            _ => {}
+
+            // The castling rook is left out of the blockers, because it might be shielding the king's destination
+            let blockers = all & !rook_sq.bitboard();
+            let king_path_attacked = ((king_path & !king_sq.bitboard()) | king_dest_sq.bitboard())
+                .any(|sq| self.sq_is_attacked_with_blockers(&sq, !color, blockers));
+            if king_path_attacked {
+                continue;
+            }
+
+            callback(
+                self,
+                &Move {
+                    piece: Piece::King,
+                    from: king_sq,
+                    to: king_dest_sq,
+                    flag,
+                    promotion: None,
+                },
+            )
         }
     }
 
-    pub fn generate_moves<T>(&mut self, mut callback: T)
+    pub fn generate_moves<T>(&mut self, callback: T)
+    where
+        T: FnMut(&mut Self, &Move),
+    {
+        self.generate(MoveGenMode::All, callback)
+    }
+
+    /// Generates legal captures, en passant captures and promotions
+    pub fn generate_captures<T>(&mut self, callback: T)
+    where
+        T: FnMut(&mut Self, &Move),
+    {
+        self.generate(MoveGenMode::Captures, callback)
+    }
+
+    /// Generates legal moves that neither capture nor promote, including castling
+    pub fn generate_quiets<T>(&mut self, callback: T)
     where
         T: FnMut(&mut Self, &Move),
     {
+        self.generate(MoveGenMode::Quiets, callback)
+    }
+
+    /// Generates the legal moves that get out of check: king moves, captures of the checker and blocks.
+    /// Only king moves in double check, nothing when the active color is not in check.
+    pub fn generate_evasions<T>(&mut self, callback: T)
+    where
+        T: FnMut(&mut Self, &Move),
+    {
+        self.generate(MoveGenMode::Evasions, callback)
+    }
+
+    fn generate<T>(&mut self, mode: MoveGenMode, mut callback: T)
+    where
+        T: FnMut(&mut Self, &Move),
+    {
+        if mode == MoveGenMode::Evasions && !self.in_check() {
+            return;
+        }
         let active_color = &self.state.active_color.clone();
         let opp_color = !active_color;
         let self_king_sq = self.pieces[active_color]
@@ -217,9 +415,9 @@ impl Board {
         self.generate_any_moves(
             Piece::King,
             active_color,
-            opp_color,
             &self_king_sq,
             king_check_mask,
+            mode,
             &mut callback,
         );
 
@@ -227,44 +425,47 @@ impl Board {
             [Some(_), Some(_)] => {}
             // Only gen other moves if not in double check
             _ => {
-                self.generate_castling_moves(active_color, &mut callback);
+                if matches!(mode, MoveGenMode::All | MoveGenMode::Quiets) {
+                    self.generate_castling_moves(active_color, &mut callback);
+                }
                 self.generate_pawn_moves(
                     active_color,
                     opp_color,
                     &self_king_sq,
                     block_check_mask,
+                    mode,
                     &mut callback,
                 );
                 self.generate_any_moves(
                     Piece::Queen,
                     active_color,
-                    opp_color,
                     &self_king_sq,
                     block_check_mask,
+                    mode,
                     &mut callback,
                 );
                 self.generate_any_moves(
                     Piece::Rook,
                     active_color,
-                    opp_color,
                     &self_king_sq,
                     block_check_mask,
+                    mode,
                     &mut callback,
                 );
                 self.generate_any_moves(
                     Piece::Bishop,
                     active_color,
-                    opp_color,
                     &self_king_sq,
                     block_check_mask,
+                    mode,
                     &mut callback,
                 );
                 self.generate_any_moves(
                     Piece::Knight,
                     active_color,
-                    opp_color,
                     &self_king_sq,
                     block_check_mask,
+                    mode,
                     &mut callback,
                 );
             }
@@ -275,32 +476,26 @@ impl Board {
         &mut self,
         piece: Piece,
         active_color: &Color,
-        opp_color: &Color,
         self_king_sq: &Square,
         check_mask: Option<Bitboard>,
+        mode: MoveGenMode,
         mut callback: T,
     ) where
         T: FnMut(&mut Self, &Move),
     {
+        let opp_color = !active_color;
         let opp_all = self.pieces[opp_color].all;
         let self_all = self.pieces[active_color].all;
+        let targets = mode.targets(opp_all, check_mask) & !self_all;
 
         let piece_bb = self.pieces[active_color][&piece];
 
         for from in piece_bb {
-            let mut moves = self.get_attacks(&piece, active_color, &from) & !self_all;
-
-            if let Some(check_mask) = check_mask {
-                moves &= check_mask;
-            };
+            let mut moves = self.get_attacks(&piece, active_color, &from) & targets;
 
             if piece == Piece::King {
-                moves = moves.into_iter().fold(Bitboard::EMPTY, |mut acc, sq| {
-                    if !self.sq_is_attacked_by(&sq, opp_color) {
-                        acc |= sq.bitboard()
-                    }
-                    acc
-                })
+                // The attack map stops at the king, its check mask leaves out the squares behind it on a checking ray
+                moves &= !self.attacked_squares(opp_color);
             };
 
             if self.pinned.has_sq(from) {
@@ -323,11 +518,8 @@ impl Board {
             for to in normal_moves {
                 move_caller(self, to, MoveFlag::None);
             }
-            for p in Piece::ALL {
-                let captures = moves & self.pieces[opp_color][&p];
-                for to in captures {
-                    move_caller(self, to, MoveFlag::Capture(p));
-                }
+            for to in moves & opp_all {
+                move_caller(self, to, self.capture_flag(to));
             }
         }
     }
@@ -338,6 +530,7 @@ impl Board {
         opp_color: &Color,
         self_king_sq: &Square,
         check_mask: Option<Bitboard>,
+        mode: MoveGenMode,
         mut callback: T,
     ) where
         T: FnMut(&mut Self, &Move),
@@ -345,10 +538,13 @@ impl Board {
         let opp_all = self.pieces[opp_color].all;
         let self_all = self.pieces[active_color].all;
         let pawns = self.pieces[active_color].pawn;
+        let gen_captures = mode != MoveGenMode::Quiets;
+        let gen_quiets = mode != MoveGenMode::Captures;
+        let promotion_rank = Rank::Eighth.pov(active_color);
 
         for from in pawns {
             let mut move_caller = |board: &mut Self, to: Square, flag: MoveFlag| {
-                if to.rank() == Rank::Eighth.pov(active_color) {
+                if to.rank() == promotion_rank {
                     for p in Promotion::ALL {
                         callback(
                             board,
@@ -380,16 +576,13 @@ impl Board {
             } else {
                 Bitboard::FULL
             };
-            let check_mask = if let Some(check_mask) = check_mask {
-                check_mask
-            } else {
-                Bitboard::FULL
-            };
+            let check_mask = mode.check_mask(check_mask);
             let mask = pin_mask & check_mask;
 
             // no check mask for now because ep can capture checker without target square being in check mask
             let captures = pawn_attacks(*active_color, &from) & pin_mask;
-            if let Some(ep_file) = self.state.ep_file {
+            let ep_file = self.state.ep_file.filter(|_| gen_captures);
+            if let Some(ep_file) = ep_file {
                 let to = Square::ep_move_sq(opp_color, ep_file);
                 let ep_pawn_sq = Square::ep_pawn_sq(opp_color, ep_file);
                 if captures.has_sq(to) && check_mask.has_sq(ep_pawn_sq) {
@@ -415,13 +608,13 @@ impl Board {
                 }
             }
             // add check mask for other captures
-            let captures = captures & check_mask & opp_all;
+            let captures = if gen_captures {
+                captures & check_mask & opp_all
+            } else {
+                Bitboard::EMPTY
+            };
             for to in captures {
-                for piece in &Piece::ALL {
-                    if self.pieces[opp_color][piece].has_sq(to) {
-                        move_caller(self, to, MoveFlag::Capture(*piece))
-                    }
-                }
+                move_caller(self, to, self.capture_flag(to))
             }
             let to = from.increment(match active_color {
                 Color::White => 8,
@@ -432,10 +625,15 @@ impl Board {
             //     println!("CHECK MASK {} PIN MASK{}", check_mask.unwrap_or(Bitboard::FULL), self.pinned);
             // }
 
-            if (mask & !(self_all | opp_all)).has_sq(to) {
+            let push_wanted = if to.rank() == promotion_rank {
+                gen_captures
+            } else {
+                gen_quiets
+            };
+            if push_wanted && (mask & !(self_all | opp_all)).has_sq(to) {
                 move_caller(self, to, MoveFlag::None)
             }
-            if from.rank() == Rank::Second.pov(active_color) {
+            if gen_quiets && from.rank() == Rank::Second.pov(active_color) {
                 let to = from.increment(match active_color {
                     Color::White => 16,
                     Color::Black => -16,
@@ -450,9 +648,57 @@ impl Board {
         }
     }
 
+    /// Adds or removes a piece. A piece can only be added to an empty square,
+    /// so a captured piece has to be removed before the capturing piece is placed.
     pub fn toggle_sq(&mut self, color: &Color, piece: &Piece, sq: &Square) {
         self.pieces[color][piece] ^= sq.bitboard();
         self.pieces[color].all ^= sq.bitboard();
+        self.zobrist ^= zobrist::piece_key(color, piece, sq);
+
+        let slot = &mut self.mailbox[sq.idx()];
+        if *slot == Some((*color, *piece)) {
+            *slot = None;
+        } else {
+            debug_assert_eq!(*slot, None, "placing {color:?} {piece:?} on occupied {sq:?}");
+            *slot = Some((*color, *piece));
+        }
+    }
+
+    /// Rebuilds the mailbox from the bitboards
+    pub(crate) fn update_mailbox(&mut self) {
+        self.mailbox = [None; 64];
+        for color in &Color::ALL {
+            for piece in &Piece::ALL {
+                for sq in self.pieces[color][piece] {
+                    self.mailbox[sq.idx()] = Some((*color, *piece));
+                }
+            }
+        }
+    }
+
+    /// Flag of a capture on `sq`, which has to hold an opponent's piece
+    fn capture_flag(&self, sq: Square) -> MoveFlag {
+        match self.mailbox[sq.idx()] {
+            Some((_, piece)) => MoveFlag::Capture(piece),
+            None => unreachable!("captures always target a piece"),
+        }
+    }
+
+    /// Computes the zobrist key of the position from scratch.
+    /// `make_move` keeps `self.zobrist` up to date incrementally, so this is only needed when constructing a board.
+    pub fn compute_zobrist(&self) -> u64 {
+        let mut key = 0;
+        for color in &Color::ALL {
+            for piece in &Piece::ALL {
+                for sq in self.pieces[color][piece] {
+                    key ^= zobrist::piece_key(color, piece, &sq);
+                }
+            }
+        }
+        key ^= zobrist::castling_key(&self.state.castling);
+        key ^= zobrist::ep_file_key(self.state.ep_file);
+        key ^= zobrist::active_color_key(&self.state.active_color);
+        key
     }
 
     fn add_checker(&mut self, input: (Square, Bitboard, Option<Bitboard>)) {
@@ -466,14 +712,11 @@ impl Board {
 
     pub fn update_slider_checks_pins(&mut self, color: &Color) {
         self.pinned = Bitboard::EMPTY;
-        let opp_king_sq = self.pieces[&!color]
-            .king
-            .clone()
-            .next_sq()
-            .unwrap_or_else(|| {
-                self.pretty_print(false);
-                panic!("king should always exist");
-            });
+        // Only possible in positions that fail `validate`
+        let mut opp_king = self.pieces[!color].king;
+        let Some(opp_king_sq) = opp_king.next_sq() else {
+            return;
+        };
         let self_all = self.pieces[color].all;
         let opp_all = self.pieces[!color].all;
         let self_qb = self.pieces[color].queen | self.pieces[color].bishop;
@@ -495,18 +738,84 @@ impl Board {
         }
     }
 
-    // fn update_attacks(&self, color: &Color, mv: &Move) {
-    //     let attacks_before = Self::get_attacks(color, mv.from);
-    //     let attacks_after = Self::get_attacks(color, mv.to);
+    /// Computes `pinned` and `check_masks` from scratch for the side to move.
+    /// `make_move` only looks for checks by the moved piece, this also finds knight and pawn checks of a new position.
+    pub(crate) fn update_checks_pins(&mut self) {
+        let active_color = &self.state.active_color.clone();
+        let opp_color = !active_color;
+        self.check_masks = [None, None];
+        self.pinned = Bitboard::EMPTY;
+        let mut king = self.pieces[active_color].king;
+        let Some(king_sq) = king.next_sq() else {
+            return;
+        };
+        let occupancy = self.pieces[active_color].all | self.pieces[opp_color].all;
+        let checkers = self.attackers_to(&king_sq, occupancy) & self.pieces[opp_color].all;
+        // `check_masks` can only hold two checkers, `validate` reports more than that
+        if checkers.sq_count() > 2 {
+            return;
+        }
 
-    //     for to in attacks_before {
-    //         if !self.sq_is_attacked(color, to) {
-    //             self.attacks[color] ^= to.bitboard()
-    //         }
-    //     }
+        for sq in checkers & self.pieces[opp_color].knight {
+            self.add_checker((sq, knight_moves(&sq), None));
+        }
+        for sq in checkers & self.pieces[opp_color].pawn {
+            self.add_checker((sq, pawn_attacks(*opp_color, &sq), None));
+        }
+        self.update_slider_checks_pins(opp_color);
+    }
 
-    //     self.attacks[color] |= attacks_after.bitboard();
-    // }
+    /// Brings the attack maps up to date with the pieces, which were `before` at the last update.
+    /// The leaper attacks of a color only change when its pawns, knights or king do. Its slider attacks also change
+    /// when a square inside them is emptied or filled, which changes the rays, but not when a piece is captured.
+    pub(crate) fn update_attacks(&mut self, before: &BothColors<PieceBitboards>) {
+        let occupancy = |pieces: &BothColors<PieceBitboards>| pieces[&Color::White].all | pieces[&Color::Black].all;
+        let occupancy_changed = occupancy(&self.pieces) ^ occupancy(before);
+        let leapers = |pieces: &PieceBitboards| pieces.pawn | pieces.knight | pieces.king;
+        let sliders = |pieces: &PieceBitboards| pieces.bishop | pieces.rook | pieces.queen;
+        for color in &Color::ALL {
+            let (pieces, before) = (&self.pieces[color], &before[color]);
+            if leapers(pieces) != leapers(before) {
+                self.leaper_attacks[color] = self.compute_leaper_attacks(color);
+            }
+            if sliders(pieces) != sliders(before) || !(self.slider_attacks[color] & occupancy_changed).is_empty() {
+                self.slider_attacks[color] = self.compute_slider_attacks(color);
+            }
+        }
+    }
+
+    fn compute_leaper_attacks(&self, color: &Color) -> Bitboard {
+        let pieces = &self.pieces[color];
+        let mut attacks = Bitboard::EMPTY;
+        for sq in pieces.pawn {
+            attacks |= pawn_attacks(*color, &sq);
+        }
+        for sq in pieces.knight {
+            attacks |= knight_moves(&sq);
+        }
+        for sq in pieces.king {
+            attacks |= king_moves(&sq);
+        }
+        attacks
+    }
+
+    fn compute_slider_attacks(&self, color: &Color) -> Bitboard {
+        let occupancy = self.pieces[&Color::White].all | self.pieces[&Color::Black].all;
+        let pieces = &self.pieces[color];
+        let mut attacks = Bitboard::EMPTY;
+        for sq in pieces.bishop | pieces.queen {
+            attacks |= bishop_moves(&sq, occupancy);
+        }
+        for sq in pieces.rook | pieces.queen {
+            attacks |= rook_moves(&sq, occupancy);
+        }
+        attacks
+    }
+
+    /// Squares attacked by `color`, whether or not they hold a piece
+    pub fn attacked_squares(&self, color: &Color) -> Bitboard {
+        self.leaper_attacks[color] | self.slider_attacks[color]
+    }
 
     fn get_attacks(&self, piece: &Piece, color: &Color, sq: &Square) -> Bitboard {
         let blockers = self.pieces[color].all | self.pieces[!color].all;
@@ -520,108 +829,108 @@ impl Board {
         }
     }
 
-    fn sq_is_attacked_by(&self, sq: &Square, color: &Color) -> bool {
-        for p in &Piece::ALL {
-            let attacks = self.get_attacks(p, !color, sq);
-            let pieces = self.pieces[color][p];
-            // println!("SQ {:?} PIECE {:?} PIECES {} ATTAKCS {}", sq, p, pieces, attacks);
-            if !(pieces & attacks).is_empty() {
-                return true;
-            }
-        }
-        false
+    /// Pieces of both colors attacking `sq`, with sliders blocked by `occupancy` instead of the pieces on the board.
+    /// Mask the result with `pieces[color].all` for a single color.
+    pub fn attackers_to(&self, sq: &Square, occupancy: Bitboard) -> Bitboard {
+        let (white, black) = (&self.pieces[&Color::White], &self.pieces[&Color::Black]);
+        (pawn_attacks(Color::Black, sq) & white.pawn)
+            | (pawn_attacks(Color::White, sq) & black.pawn)
+            | (knight_moves(sq) & (white.knight | black.knight))
+            | (king_moves(sq) & (white.king | black.king))
+            | (bishop_moves(sq, occupancy) & (white.bishop | white.queen | black.bishop | black.queen))
+            | (rook_moves(sq, occupancy) & (white.rook | white.queen | black.rook | black.queen))
     }
 
-    fn toggle_kingside_castles(&mut self, color: &Color) {
-        let c = &mut self.state.castling[color].king_side;
-        *c = !*c;
-        self.toggle_sq(
-            color,
-            &Piece::Rook,
-            &Square::from_rank_file(Rank::First.pov(color), File::H),
-        );
-        self.toggle_sq(
-            color,
-            &Piece::Rook,
-            &Square::from_rank_file(Rank::First.pov(color), File::F),
-        )
-    }
-
-    fn toggle_queenside_castles(&mut self, color: &Color) {
-        let c = &mut self.state.castling[color].queen_side;
-        *c = !*c;
-        self.toggle_sq(
-            color,
-            &Piece::Rook,
-            &Square::from_rank_file(Rank::First.pov(color), File::A),
-        );
-        self.toggle_sq(
-            color,
-            &Piece::Rook,
-            &Square::from_rank_file(Rank::First.pov(color), File::D),
-        );
+    fn sq_is_attacked_with_blockers(&self, sq: &Square, color: &Color, blockers: Bitboard) -> bool {
+        let pieces = &self.pieces[color];
+        !((pawn_attacks(*!color, sq) & pieces.pawn)
+            | (knight_moves(sq) & pieces.knight)
+            | (king_moves(sq) & pieces.king)
+            | (bishop_moves(sq, blockers) & (pieces.bishop | pieces.queen))
+            | (rook_moves(sq, blockers) & (pieces.rook | pieces.queen)))
+            .is_empty()
     }
 
-    pub fn make_move(&mut self, mv: &Move) {
+    /// Moves the castling rook of `flag` between its start square and its destination.
+    /// `castling` has to be the castling rights from before the castling move, since they hold the rook's start file.
+    fn toggle_castling_rook(&mut self, color: &Color, flag: &MoveFlag, castling: &Castling) {
+        let (rook_file, rook_dest_file) = match flag {
+            MoveFlag::KingSideCastles => (castling.king_side, File::F),
+            MoveFlag::QueenSideCastles => (castling.queen_side, File::D),
+            _ => unreachable!("only castling moves have a castling rook"),
+        };
+        let rook_file = rook_file.expect("castling requires castling rights");
+        let rank = Rank::First.pov(color);
+        self.toggle_sq(color, &Piece::Rook, &Square::from_rank_file(rank, rook_file));
+        self.toggle_sq(color, &Piece::Rook, &Square::from_rank_file(rank, rook_dest_file));
+    }
+
+    pub fn make_move(&mut self, mv: &Move) -> Undo {
+        let undo = Undo {
+            state: self.state,
+            pinned: self.pinned,
+            check_masks: self.check_masks,
+            zobrist: self.zobrist,
+            leaper_attacks: self.leaper_attacks,
+            slider_attacks: self.slider_attacks,
+        };
         self.check_masks = [None, None];
         let active_color = &self.state.active_color.clone();
         let opp_color = !active_color;
+        let castling_before = self.state.castling;
+        let pieces_before = self.pieces;
+        let resets_half_move_count = mv.piece == Piece::Pawn
+            || matches!(mv.flag, MoveFlag::Capture(_) | MoveFlag::EnPassant);
 
+        self.zobrist ^= zobrist::ep_file_key(self.state.ep_file);
         self.state.ep_file = None;
         self.toggle_sq(active_color, &mv.piece, &mv.from);
 
-        match mv.promotion {
-            Some(Promotion::Queen) => self.toggle_sq(active_color, &Piece::Queen, &mv.to),
-            Some(Promotion::Rook) => self.toggle_sq(active_color, &Piece::Rook, &mv.to),
-            Some(Promotion::Bishop) => self.toggle_sq(active_color, &Piece::Bishop, &mv.to),
-            Some(Promotion::Knight) => {
-                self.toggle_sq(active_color, &Piece::Knight, &mv.to);
-                let moves = knight_moves(&mv.to);
-                if !(moves & self.pieces[opp_color].king).is_empty() {
-                    self.add_checker((mv.to, moves, None));
-                }
-            },
-            None => self.toggle_sq(active_color, &mv.piece, &mv.to),
-        }
-
+        // Captured pieces and the castling rook leave before the moving piece is placed
         match &mv.flag {
             MoveFlag::None => {}
             MoveFlag::Capture(piece) => {
                 self.toggle_sq(opp_color, piece, &mv.to);
                 if *piece == Piece::Rook && mv.to.rank() == Rank::First.pov(opp_color) {
-                    let c = &mut self.state.castling[opp_color];
-                    match mv.to.file() {
-                        File::A => c.queen_side = false,
-                        File::H => c.king_side = false,
-                        _ => {}
-                    }
+                    self.state.castling[opp_color].remove_rook(mv.to.file());
                 }
             }
-            MoveFlag::PawnFirstMove => self.state.ep_file = Some(mv.to.file()),
+            MoveFlag::PawnFirstMove => {
+                self.state.ep_file = Some(mv.to.file());
+                self.zobrist ^= zobrist::ep_file_key(self.state.ep_file);
+            }
             MoveFlag::EnPassant => self.toggle_sq(
                 opp_color,
                 &Piece::Pawn,
                 &Square::ep_pawn_sq(opp_color, mv.to.file()),
             ),
-            MoveFlag::KingSideCastles => self.toggle_kingside_castles(active_color),
-            MoveFlag::QueenSideCastles => self.toggle_queenside_castles(active_color),
+            MoveFlag::KingSideCastles | MoveFlag::QueenSideCastles => {
+                self.toggle_castling_rook(active_color, &mv.flag, &castling_before[active_color])
+            }
+        }
+
+        match mv.promotion {
+            Some(Promotion::Queen) => self.toggle_sq(active_color, &Piece::Queen, &mv.to),
+            Some(Promotion::Rook) => self.toggle_sq(active_color, &Piece::Rook, &mv.to),
+            Some(Promotion::Bishop) => self.toggle_sq(active_color, &Piece::Bishop, &mv.to),
+            Some(Promotion::Knight) => {
+                self.toggle_sq(active_color, &Piece::Knight, &mv.to);
+                let moves = knight_moves(&mv.to);
+                if !(moves & self.pieces[opp_color].king).is_empty() {
+                    self.add_checker((mv.to, moves, None));
+                }
+            },
+            None => self.toggle_sq(active_color, &mv.piece, &mv.to),
         }
 
         match mv.piece {
             Piece::King => {
                 let c = &mut self.state.castling[active_color];
-                c.king_side = false;
-                c.queen_side = false;
+                c.king_side = None;
+                c.queen_side = None;
             }
-            Piece::Rook => {
-                let c = &mut self.state.castling[active_color];
-                if mv.from.rank() == Rank::First.pov(active_color) {
-                    match mv.from.file() {
-                        File::A => c.queen_side = false,
-                        File::H => c.king_side = false,
-                        _ => {}
-                    }
-                }
+            Piece::Rook if mv.from.rank() == Rank::First.pov(active_color) => {
+                self.state.castling[active_color].remove_rook(mv.from.file());
             }
             Piece::Knight => {
                 let moves = knight_moves(&mv.to);
@@ -640,12 +949,53 @@ impl Board {
         }
 
         self.update_slider_checks_pins(active_color);
+        self.update_attacks(&pieces_before);
 
-        self.state.half_move_count += 1;
+        self.zobrist ^= zobrist::castling_key(&castling_before) ^ zobrist::castling_key(&self.state.castling);
+        self.zobrist ^= zobrist::active_color_key(active_color) ^ zobrist::active_color_key(opp_color);
+
+        if resets_half_move_count {
+            self.state.half_move_count = 0;
+        } else {
+            self.state.half_move_count += 1;
+        }
         if *active_color == Color::Black {
             self.state.full_move_count += 1;
         }
         self.state.active_color = *opp_color;
+        undo
+    }
+
+    /// Takes back `mv`, which must be the last move made on this board.
+    /// `undo` is the value returned by the `make_move` call that made it.
+    pub fn unmake_move(&mut self, mv: &Move, undo: Undo) {
+        let active_color = &undo.state.active_color;
+        let opp_color = !active_color;
+
+        let to_piece = mv.promotion.map_or(mv.piece, |p| p.piece());
+        self.toggle_sq(active_color, &to_piece, &mv.to);
+
+        match &mv.flag {
+            MoveFlag::None | MoveFlag::PawnFirstMove => {}
+            MoveFlag::Capture(piece) => self.toggle_sq(opp_color, piece, &mv.to),
+            MoveFlag::EnPassant => self.toggle_sq(
+                opp_color,
+                &Piece::Pawn,
+                &Square::ep_pawn_sq(opp_color, mv.to.file()),
+            ),
+            MoveFlag::KingSideCastles | MoveFlag::QueenSideCastles => {
+                self.toggle_castling_rook(active_color, &mv.flag, &undo.state.castling[active_color])
+            }
+        }
+
+        self.toggle_sq(active_color, &mv.piece, &mv.from);
+
+        self.state = undo.state;
+        self.pinned = undo.pinned;
+        self.check_masks = undo.check_masks;
+        self.zobrist = undo.zobrist;
+        self.leaper_attacks = undo.leaper_attacks;
+        self.slider_attacks = undo.slider_attacks;
     }
 
     fn generate_moves_recursively<F>(&mut self, max_ply: u8, current_ply: u8, on_move: &mut F)
@@ -657,6 +1007,13 @@ impl Board {
             if current_ply < max_ply {
                 let mut new_board = board.clone();
                 new_board.make_move(mv);
+                debug_assert_eq!(
+                    new_board.zobrist,
+                    new_board.compute_zobrist(),
+                    "incremental zobrist key diverged after {} in {}",
+                    mv.to_string(),
+                    board.fen(false)
+                );
                 new_board.generate_moves_recursively(max_ply, current_ply + 1, on_move);
             }
         };
@@ -670,94 +1027,6 @@ impl Board {
         };
         self.generate_moves_recursively(ply, 1, &mut on_move)
     }
-    
-    pub fn perft_multithread(&mut self, ply: u8, thread_count: usize) -> usize {
-        let mut moves = Vec::with_capacity(30);
-        let mut total = 0;
-        let start = std::time::Instant::now();
-        let get_first_moves = |_: &mut Self, mv: &Move| {
-            moves.push(*mv);
-        };
-        
-        self.generate_moves(get_first_moves);
-        
-        for moves in moves.chunks(thread_count) {
-            let mut threads = Vec::with_capacity(thread_count);
-            for mv in moves {
-                let mut board = self.clone();
-                let mv = *mv;
-                threads.push((mv, thread::spawn(move||{
-                    let mut mv_total = 0;
-                    board.make_move(&mv);
-                    if ply >= 2 {
-                        let mut on_move = |_: &mut Self, _: &Move, current_ply: u8| {
-                            if &current_ply == &ply {
-                                mv_total+=1;
-                            }
-                        };
-                        board.generate_moves_recursively(ply, 2, &mut on_move)
-                    }
-                    
-                    match mv_total {
-                        0 if ply == 1 => 1,
-                        t => t
-                    }
-                })));
-            }
-            for (mv, t) in threads {
-                let mv_total=t.join().expect("joining thread is fine");
-                println!("{}: {}", mv.to_string(), mv_total);
-                total+=mv_total;
-            }
-        }
-        let end = start.elapsed();
-        let m_moves_per_sec = 10f64.powf(-6.0) * total as f64/end.as_secs_f64();
-        println!("Total: {}", total);
-        println!("Million moves/sec: {}", m_moves_per_sec);
-        println!("Million moves/sec/thread ({thread_count} threads): {}", m_moves_per_sec/thread_count as f64);
-        total
-    }
-
-    pub fn perft(&mut self, ply: u8) -> usize {
-        let mut curr_move: Option<(String, usize)> = None;
-        let mut total = 0;
-        let start = std::time::Instant::now();
-        let mut on_move = |_: &mut Self, mv: &Move, current_ply: u8| {
-            // println!("{}{}", "  ".repeat(current_ply as usize), mv.to_string());
-            if let Some((mv, count)) = &mut curr_move {
-                if current_ply == 1 {
-                    let count = match count {
-                        0 => 1,
-                        c => *c,
-                    };
-                    println!("{}: {}", mv, count);
-                    total+=count;
-                    curr_move = None;
-                } else if current_ply == ply {
-                    *count += 1;
-                }
-            } 
-            if curr_move.is_none() {
-                curr_move = Some((mv.to_string(), 0))
-            }
-        };
-
-        self.generate_moves_recursively(ply, 1, &mut on_move);
-
-        if let Some((mv, count)) = curr_move {
-            let count = match count {
-                0 => 1,
-                c => c,
-            };
-            println!("{}: {}", mv, count);
-            total+=count;
-        }
-        
-        let end = start.elapsed();
-        println!("Total: {}", total);
-        println!("Million moves/sec: {}", 10f64.powf(-6.0) * total as f64/end.as_secs_f64());
-        total
-    }
 
     pub fn pretty_print(&self, should_erase: bool) {
         if should_erase {
@@ -769,7 +1038,7 @@ impl Board {
         for rank in Rank::ALL.iter().rev() {
             let mut to_print = String::from("|");
             for file in File::ALL {
-                if let Some((c, p)) = self.get_sq(Square::from_rank_file(*rank, file)) {
+                if let Some((c, p)) = self.piece_at(Square::from_rank_file(*rank, file)) {
                     let mut char = p.to_char();
                     if c == Color::White {
                         char.make_ascii_uppercase()
@@ -789,7 +1058,7 @@ impl Board {
         println!(
             "\rEn passant square: {}        \r",
             if let Some(ep_file) = self.state.ep_file {
-                Square::ep_move_sq(&self.state.active_color, ep_file).to_string()
+                Square::ep_move_sq(!&self.state.active_color, ep_file).to_string()
             } else {
                 "-".to_owned()
             }
@@ -809,8 +1078,302 @@ impl Board {
 }
 
 #[cfg(test)]
-mod tests {
-    use crate::board::Board;
+pub(crate) mod tests {
+    use util::{bitboard::Bitboard, color::Color, piece::Piece};
+
+    use crate::{board::{Board, DrawReason, Status}, history::History, mv::{Move, MoveFlag}};
+
+    /// Calls `f` on every position reachable from `fens` in at most `depth` plies
+    pub(crate) fn for_each_node<F>(fens: &[&str], depth: u8, mut f: F)
+    where
+        F: FnMut(&mut Board),
+    {
+        fn visit<F: FnMut(&mut Board)>(board: &mut Board, depth: u8, f: &mut F) {
+            f(board);
+            if depth > 0 {
+                for mv in board.get_moves().iter() {
+                    let undo = board.make_move(mv);
+                    visit(board, depth - 1, f);
+                    board.unmake_move(mv, undo);
+                }
+            }
+        }
+        for fen in fens {
+            let mut board = Board::from_fen(fen).expect("fen is valid");
+            visit(&mut board, depth, &mut f);
+        }
+    }
+
+    fn play_moves(board: &mut Board, moves: &[&str]) {
+        for mv in moves {
+            let mv = Move::from_str(mv, board).expect("move is legal");
+            board.make_move(&mv);
+        }
+    }
+
+    /// Like `play_moves`, also recording the positions in `history`
+    fn play_recorded(board: &mut Board, history: &mut History, moves: &[&str]) {
+        for mv in moves {
+            play_moves(board, &[mv]);
+            history.push(board.zobrist);
+        }
+    }
+
+    #[test]
+    fn zobrist_transpositions() {
+        let mut board1 = Board::default();
+        let mut board2 = Board::default();
+        play_moves(&mut board1, &["g1f3", "b8c6", "b1c3", "g8f6"]);
+        play_moves(&mut board2, &["b1c3", "g8f6", "g1f3", "b8c6"]);
+        assert_eq!(board1.zobrist, board2.zobrist);
+        assert_eq!(board1.zobrist, board1.compute_zobrist());
+
+        play_moves(&mut board1, &["f3g1", "c6b8", "c3b1", "f6g8"]);
+        assert_eq!(board1.zobrist, Board::default().zobrist);
+        assert_ne!(board1.zobrist, board2.zobrist);
+    }
+
+    #[test]
+    fn zobrist_state() {
+        let mut ep = Board::default();
+        let mut no_ep = Board::default();
+        play_moves(&mut ep, &["e2e4", "g8f6", "g1f3", "f6g8", "f3g1"]);
+        play_moves(&mut no_ep, &["g1f3", "g8f6", "e2e4", "f6g8", "f3g1"]);
+        assert_eq!(ep.zobrist, no_ep.zobrist);
+
+        let mut ep = Board::default();
+        play_moves(&mut ep, &["e2e4"]);
+        let mut no_ep = Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")
+            .expect("fen is valid");
+        assert_ne!(ep.zobrist, no_ep.zobrist);
+        play_moves(&mut no_ep, &["g8f6"]);
+        play_moves(&mut ep, &["g8f6"]);
+        assert_eq!(ep.zobrist, no_ep.zobrist);
+
+        let mut castled =
+            Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").expect("fen is valid");
+        let mut moved = castled;
+        play_moves(&mut castled, &["e1g1", "e8c8"]);
+        play_moves(&mut moved, &["e1f1", "a8b8", "h1h8", "e8d7"]);
+        assert_eq!(castled.zobrist, castled.compute_zobrist());
+        assert_eq!(moved.zobrist, moved.compute_zobrist());
+    }
+
+    fn draw_reason_with(board: &mut Board, history: &History) -> Option<DrawReason> {
+        match board.status(history) {
+            Status::Draw(reason) => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// The draw reason of the position on its own, without earlier positions to repeat
+    fn draw_reason(board: &mut Board) -> Option<DrawReason> {
+        draw_reason_with(board, &History::new(board))
+    }
+
+    #[test]
+    fn fifty_move_rule() {
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 98 80").expect("fen is valid");
+        play_moves(&mut board, &["a1a2"]);
+        assert_eq!(draw_reason(&mut board), None);
+        play_moves(&mut board, &["e8d8"]);
+        assert_eq!(board.state.half_move_count, 100);
+        assert_eq!(draw_reason(&mut board), Some(DrawReason::FiftyMoveRule));
+
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 98 80").expect("fen is valid");
+        play_moves(&mut board, &["e2e4", "e8d8"]);
+        assert_eq!(board.state.half_move_count, 1);
+        assert_eq!(draw_reason(&mut board), None);
+
+        let mut board = Board::from_fen("r3k3/8/8/8/8/8/8/R3K3 w - - 98 80").expect("fen is valid");
+        play_moves(&mut board, &["a1a8", "e8e7"]);
+        assert_eq!(board.state.half_move_count, 1);
+
+        // checkmate takes precedence over the fifty move rule
+        let mut board = Board::from_fen("7k/8/6K1/8/8/8/8/R7 w - - 99 80").expect("fen is valid");
+        play_moves(&mut board, &["a1a8"]);
+        let history = History::new(&board);
+        assert!(matches!(board.status(&history), Status::Checkmate));
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let mut board = Board::default();
+        let mut history = History::new(&board);
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        play_recorded(&mut board, &mut history, &shuffle);
+        assert_eq!(draw_reason_with(&mut board, &history), None);
+        play_recorded(&mut board, &mut history, &shuffle);
+        assert_eq!(draw_reason_with(&mut board, &history), Some(DrawReason::ThreefoldRepetition));
+        // without the earlier positions there is no repetition
+        assert_eq!(draw_reason(&mut board), None);
+
+        // a pawn move in between makes earlier positions unreachable
+        let mut board = Board::default();
+        let mut history = History::new(&board);
+        play_recorded(&mut board, &mut history, &shuffle);
+        play_recorded(&mut board, &mut history, &["e2e3", "e7e6"]);
+        play_recorded(&mut board, &mut history, &shuffle);
+        assert_eq!(draw_reason_with(&mut board, &history), None);
+        play_recorded(&mut board, &mut history, &shuffle);
+        assert_eq!(draw_reason_with(&mut board, &history), Some(DrawReason::ThreefoldRepetition));
+
+        // popping positions forgets them again
+        let mut board = Board::default();
+        let mut history = History::new(&board);
+        play_recorded(&mut board, &mut history, &shuffle);
+        play_recorded(&mut board, &mut history, &shuffle[..3]);
+        let mv = Move::from_str(shuffle[3], &mut board).expect("move is legal");
+        let undo = board.make_move(&mv);
+        history.push(board.zobrist);
+        assert_eq!(draw_reason_with(&mut board, &history), Some(DrawReason::ThreefoldRepetition));
+        board.unmake_move(&mv, undo);
+        history.pop();
+        assert_eq!(draw_reason_with(&mut board, &history), None);
+    }
+
+    #[test]
+    fn insufficient_material() {
+        for (fen, insufficient) in [
+            ("8/8/4k3/8/8/3K4/8/8 w - - 0 1", true),
+            ("8/8/4k3/8/8/3K4/8/6N1 w - - 0 1", true),
+            ("8/8/4k3/8/8/3K4/8/5b2 w - - 0 1", true),
+            ("8/8/4k3/8/2B5/3K4/8/5b2 w - - 0 1", true),
+            ("8/8/4k3/8/2B5/3K4/B7/5b2 w - - 0 1", true),
+            ("8/8/4k3/8/8/3KB3/8/5b2 w - - 0 1", false),
+            ("8/8/4k3/8/8/3K4/8/5bN1 w - - 0 1", false),
+            ("8/8/4k3/8/8/3K4/8/4nN2 w - - 0 1", false),
+            ("8/8/4k3/8/8/3K4/8/6NN w - - 0 1", false),
+            ("8/8/4k3/8/8/3K4/P7/8 w - - 0 1", false),
+            ("8/8/4k3/8/8/3K4/8/7r w - - 0 1", false),
+        ] {
+            let mut board = Board::from_fen(fen).expect("fen is valid");
+            assert_eq!(board.has_insufficient_material(), insufficient, "{fen}");
+            let expected = insufficient.then_some(DrawReason::InsufficientMaterial);
+            assert_eq!(draw_reason(&mut board), expected, "{fen}");
+        }
+    }
+
+    #[test]
+    fn gen_modes() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1",
+            "2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1",
+            // double check
+            "4r1k1/8/8/8/8/3n4/8/R3K2R w KQ - 0 1",
+        ];
+        for_each_node(&fens, 2, |board| {
+            let mut all = Vec::new();
+            let mut captures = Vec::new();
+            let mut quiets = Vec::new();
+            let mut evasions = Vec::new();
+            board.generate_moves(|_, mv| all.push(*mv));
+            board.generate_captures(|_, mv| captures.push(*mv));
+            board.generate_quiets(|_, mv| quiets.push(*mv));
+            board.generate_evasions(|_, mv| evasions.push(*mv));
+
+            let fen = board.fen(false);
+            assert_eq!(captures.len() + quiets.len(), all.len(), "{fen}");
+            for mv in &all {
+                let is_capture = matches!(mv.flag, MoveFlag::Capture(_) | MoveFlag::EnPassant)
+                    || mv.promotion.is_some();
+                assert_eq!(captures.contains(mv), is_capture, "{} in {fen}", mv.to_string());
+                assert_eq!(quiets.contains(mv), !is_capture, "{} in {fen}", mv.to_string());
+            }
+            if board.in_check() {
+                assert_eq!(evasions, all, "{fen}");
+                if let [Some(_), Some(_)] = board.check_masks {
+                    assert!(evasions.iter().all(|mv| mv.piece == Piece::King), "{fen}");
+                }
+            } else {
+                assert!(evasions.is_empty(), "{fen}");
+            }
+        });
+    }
+
+    #[test]
+    fn unmake_move() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -",
+            "8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1",
+        ];
+        for_each_node(&fens, 2, |board| {
+            let before = *board;
+            let fen = before.fen(false);
+            for mv in board.get_moves().iter() {
+                let undo = board.make_move(mv);
+                assert_eq!(board.zobrist, board.compute_zobrist(), "zobrist after {} in {fen}", mv.to_string());
+                board.unmake_move(mv, undo);
+                assert_eq!(*board, before, "unmaking {} in {fen}", mv.to_string());
+            }
+        });
+    }
+
+    #[test]
+    fn gives_check() {
+        for (fen, depth) in [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 3),
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -", 2),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -", 4),
+            ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 2),
+            ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", 2),
+            // castling rook checks, in standard chess and chess960
+            ("5k2/8/8/8/8/8/8/4K2R w K - 0 1", 0),
+            ("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1", 0),
+            ("3k4/8/8/8/8/8/8/1R3K2 w B - 0 1", 0),
+        ] {
+            for_each_node(&[fen], depth, |board| {
+                for mv in board.get_moves().iter() {
+                    let gives_check = board.gives_check(mv);
+                    let undo = board.make_move(mv);
+                    assert_eq!(gives_check, board.in_check(), "{} in {}", mv.to_string(), board.fen(false));
+                    board.unmake_move(mv, undo);
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn mailbox() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9",
+        ];
+        for_each_node(&fens, 3, |board| {
+            let mut rebuilt = *board;
+            rebuilt.update_mailbox();
+            assert_eq!(board.mailbox, rebuilt.mailbox, "{}", board.fen(false));
+        });
+    }
+
+    #[test]
+    fn attack_maps() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1",
+        ];
+        for_each_node(&fens, 2, |board| {
+            let occupancy = board.pieces[&Color::White].all | board.pieces[&Color::Black].all;
+            for color in &Color::ALL {
+                let expected = Bitboard::FULL.fold(Bitboard::EMPTY, |acc, sq| {
+                    if (board.attackers_to(&sq, occupancy) & board.pieces[color].all).is_empty() {
+                        acc
+                    } else {
+                        acc | sq.bitboard()
+                    }
+                });
+                assert_eq!(board.attacked_squares(color), expected, "{:?} in {}", color, board.fen(false));
+            }
+        });
+    }
 
     #[test]
     fn starting_pos() {
@@ -878,10 +1441,68 @@ mod tests {
         assert_eq!(board.perft_multithread(5, 8), 89_941_194);
     }
     
+    #[test]
+    fn chess960() {
+        // see https://www.chessprogramming.org/Chess960_Perft_Results
+        const PERFTS: &[(&str, u8, u64)] = &[
+            ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", 4, 326_672),
+            ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", 4, 667_366),
+            ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", 4, 273_318),
+            ("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", 4, 382_958),
+            ("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", 4, 1_171_749),
+            ("qnbnr1kr/ppp1b1pp/4p3/3p1p2/8/2NPP3/PPP1BPPP/QNB1R1KR w HEhe - 1 9", 4, 824_055),
+            ("q1bnrkr1/ppppp2p/2n2p2/4b1p1/2NP4/8/PPP1PPPP/QNB1RRKB w ge - 1 9", 4, 732_757),
+            ("qbn1brkr/ppp1p1p1/2n4p/3p1p2/P7/6PP/QPPPPP2/1BNNBRKR w HFhf - 0 9", 4, 465_806),
+            ("qn1rbbkr/ppp2p1p/1n1pp1p1/8/3P4/P6P/1PP1PPPK/QNNRBB1R w hd - 2 9", 4, 679_699),
+        ];
+
+        for (i, (fen, depth, move_count)) in PERFTS.iter().enumerate() {
+            let mut board = Board::from_fen(fen).expect("fen is valid");
+            assert!(board.state.chess960, "pos {i} should be detected as chess960");
+            assert_eq!(board.perft_multithread(*depth, 8), *move_count, "failed pos {i} with fen {fen}")
+        }
+    }
+
+    #[test]
+    fn chess960_castling() {
+        // king and rook swap places, so the king's destination is only reachable by capturing its own rook
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/5KR1 w G - 0 1").expect("fen is valid");
+        assert!(board.state.chess960);
+        assert_eq!(board.fen(false), "4k3/8/8/8/8/8/8/5KR1 w K - 0 1");
+        let castles = Move::from_str("f1g1", &mut board).expect("castling is legal");
+        assert_eq!(castles.flag, MoveFlag::KingSideCastles);
+        assert_eq!(board.move_to_string(&castles), "f1g1");
+        board.make_move(&castles);
+        assert_eq!(board.fen(false), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+
+        // the castling rook is shielding the king's destination from the rook on a1
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/rRK5 w B - 0 1").expect("fen is valid");
+        assert!(board.get_moves().iter().all(|mv| mv.flag != MoveFlag::QueenSideCastles));
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/1RK5 w B - 0 1").expect("fen is valid");
+        assert!(board.get_moves().iter().any(|mv| mv.flag == MoveFlag::QueenSideCastles));
+
+        // inner rooks are written with their file
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/RR2K2R w BK - 0 1").expect("fen is valid");
+        let fen = board.fen(false);
+        assert_eq!(fen, "4k3/8/8/8/8/8/8/RR2K2R w KB - 0 1");
+        assert_eq!(Board::from_fen(&fen).expect("fen is valid").state, board.state);
+
+        // standard chess accepts both ways of writing castling
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let mut board = Board::from_fen(fen).expect("fen is valid");
+        assert!(!board.state.chess960);
+        assert_eq!(board.fen(false), fen);
+        for input in ["e1g1", "e1h1"] {
+            let mv = Move::from_str(input, &mut board).expect("castling is legal");
+            assert_eq!(mv.flag, MoveFlag::KingSideCastles);
+            assert_eq!(board.move_to_string(&mv), "e1g1");
+        }
+    }
+
     #[test]
     fn talkchess() {
         // see https://www.chessprogramming.net/perfect-perft/
-        const PERFTS: &[(&str, u8, usize)] = &[
+        const PERFTS: &[(&str, u8, u64)] = &[
             ("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1", 6, 1_134_888),
             ("8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1", 6, 1_015_133),
             ("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", 6, 1_440_467),
@@ -4,7 +4,7 @@ use util::{bitboard::Bitboard, color::Color};
 
 use crate::{piece_bb::PieceBitboards, state::Castling};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct BothColors<T> {
     black: T,
     white: T,
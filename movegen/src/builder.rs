@@ -0,0 +1,160 @@
+use util::{
+    color::Color,
+    error::ChessError,
+    piece::Piece,
+    square::{File, Square},
+};
+
+use crate::{
+    board::Board,
+    both_colors::BothColors,
+    piece_bb::PieceBitboards,
+    state::{Castling, State},
+};
+
+/// Sets up a position piece by piece. Starts from an empty board with white to move and no castling rights.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoardBuilder {
+    pieces: BothColors<PieceBitboards>,
+    state: State,
+}
+
+impl Default for BoardBuilder {
+    fn default() -> Self {
+        Self {
+            pieces: BothColors::default(),
+            state: State {
+                active_color: Color::White,
+                castling: BothColors::default(),
+                ep_file: None,
+                half_move_count: 0,
+                full_move_count: 1,
+                chess960: false,
+            },
+        }
+    }
+}
+
+impl BoardBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Puts a piece on `sq`, replacing whatever was there
+    pub fn place(mut self, sq: Square, color: Color, piece: Piece) -> Self {
+        self = self.remove(sq);
+        self.pieces[&color][&piece] |= sq.bitboard();
+        self.pieces[&color].all |= sq.bitboard();
+        self
+    }
+
+    pub fn remove(mut self, sq: Square) -> Self {
+        for color in &Color::ALL {
+            for piece in &Piece::ALL {
+                self.pieces[color][piece] &= !sq.bitboard();
+            }
+            self.pieces[color].all &= !sq.bitboard();
+        }
+        self
+    }
+
+    pub fn active_color(mut self, color: Color) -> Self {
+        self.state.active_color = color;
+        self
+    }
+
+    pub fn castling(mut self, color: Color, castling: Castling) -> Self {
+        self.state.castling[&color] = castling;
+        self
+    }
+
+    /// File of the pawn that has just moved two squares, if it can be captured en passant
+    pub fn ep_file(mut self, ep_file: Option<File>) -> Self {
+        self.state.ep_file = ep_file;
+        self
+    }
+
+    pub fn half_move_count(mut self, half_move_count: u16) -> Self {
+        self.state.half_move_count = half_move_count;
+        self
+    }
+
+    pub fn full_move_count(mut self, full_move_count: u16) -> Self {
+        self.state.full_move_count = full_move_count;
+        self
+    }
+
+    /// Writes castling as the king capturing its own rook, see `State::chess960`.
+    /// Castling rights that only exist in chess960 turn this on regardless.
+    pub fn chess960(mut self, chess960: bool) -> Self {
+        self.state.chess960 = chess960;
+        self
+    }
+
+    /// Validates the position and computes pins, checks and the zobrist key the same way `Board::from_fen` does
+    pub fn build(&self) -> Result<Board, ChessError> {
+        let mut state = self.state;
+        state.chess960 |= state.castling.is_chess960();
+        let board = Board::from_parts(self.pieces, state);
+        board.validate()?;
+        Ok(board)
+    }
+}
+
+impl Board {
+    /// A builder holding this position, for editing it
+    pub fn to_builder(&self) -> BoardBuilder {
+        BoardBuilder {
+            pieces: self.pieces,
+            state: self.state,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use util::{
+        color::Color,
+        error::ChessError,
+        piece::Piece,
+        square::{File, Square},
+    };
+
+    use crate::{board::Board, builder::BoardBuilder, state::Castling};
+
+    #[test]
+    fn builds_like_fen() {
+        let mut builder = BoardBuilder::new()
+            .place(Square::E1, Color::White, Piece::King)
+            .place(Square::H1, Color::White, Piece::Rook)
+            .place(Square::A1, Color::White, Piece::Rook)
+            .place(Square::E8, Color::Black, Piece::King)
+            .place(Square::D4, Color::Black, Piece::Pawn)
+            .place(Square::E4, Color::White, Piece::Pawn)
+            .active_color(Color::Black)
+            .castling(Color::White, Castling::STANDARD)
+            .ep_file(Some(File::E))
+            .half_move_count(0)
+            .full_move_count(12);
+        let fen = "4k3/8/8/8/3pP3/8/8/R3K2R b KQ e3 0 12";
+        let board = builder.build().expect("position is valid");
+        assert_eq!(board, Board::from_fen(fen).expect("fen is valid"));
+        assert_eq!(board.to_builder(), builder);
+
+        builder = builder.place(Square::E8, Color::Black, Piece::Queen);
+        assert_eq!(builder.build().err(), Some(ChessError::MissingKing(Color::Black)));
+        builder = builder.remove(Square::E8).place(Square::A8, Color::Black, Piece::King);
+        assert_eq!(builder.build().expect("position is valid").fen(false), "k7/8/8/8/3pP3/8/8/R3K2R b KQ e3 0 12");
+    }
+
+    #[test]
+    fn finds_checks() {
+        let mut board = Board::default()
+            .to_builder()
+            .place(Square::D3, Color::Black, Piece::Knight)
+            .build()
+            .expect("position is valid");
+        assert!(board.in_check());
+        assert!(board.get_moves().iter().all(|mv| mv.to == Square::D3 || mv.from == Square::E1));
+    }
+}
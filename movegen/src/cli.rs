@@ -1,15 +1,27 @@
 use std::collections::VecDeque;
 
 use clap::{Parser, Subcommand};
-use util::square::Square;
+use util::{error::ChessError, square::Square};
 
-use crate::{board::Board, mv::Move};
+use crate::{
+    bisect::{Bisection, DivideFile, Mismatch},
+    board::Board,
+    mv::Move,
+    parse::pgn::Game,
+    perft::{PerftSuiteEntry, PerftTable},
+    reference::{fuzz, ReferenceGenerator},
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Command,
+
+    /// Write and read castling moves as the king capturing its own rook (eg. e1h1).
+    /// Castling from any start files works without this.
+    #[arg(long, global = true)]
+    pub chess960: bool,
 }
 
 #[derive(Subcommand, Debug)]
@@ -23,7 +35,47 @@ pub enum Command {
         ply: u8,
         fen: Option<String>,
         #[arg(short, long)]
-        thread_count: Option<usize> 
+        thread_count: Option<usize>,
+        /// Size of the perft hash table in MB
+        #[arg(long)]
+        hash: Option<usize>,
+        /// Print captures, checks, mates etc. for every depth up to ply, single threaded and without a hash table
+        #[arg(long, conflicts_with_all = ["thread_count", "hash"])]
+        stats: bool,
+    },
+    /// Runs the positions of an EPD perft suite with lines like `<fen> ;D1 20 ;D2 400`.
+    /// Exits with status 1 if any count is wrong.
+    PerftSuite {
+        file: String,
+        /// Skip depths deeper than this
+        #[arg(short, long)]
+        max_depth: Option<u8>,
+        #[arg(short, long)]
+        thread_count: Option<usize>,
+        /// Size of the perft hash table in MB
+        #[arg(long)]
+        hash: Option<usize>,
+    },
+    /// Finds where perft goes wrong by following the moves whose count differs from the expected divide.
+    /// Without a file the slow reference generator provides the expected counts.
+    Bisect {
+        ply: u8,
+        fen: Option<String>,
+        /// Holds `<move>: <count>` lines for the position, the divide of positions further down
+        /// goes in sections starting with `<fen> ;D<depth>`
+        #[arg(short, long)]
+        file: Option<String>,
+    },
+    /// Plays random games and compares the generated moves with the slow reference generator in every position.
+    /// Exits with status 1 on the first mismatch.
+    Fuzz {
+        #[arg(short, long, default_value_t = 1000)]
+        games: usize,
+        /// Moves per game at most
+        #[arg(short, long, default_value_t = 200)]
+        plies: usize,
+        #[arg(short, long)]
+        seed: Option<u64>,
     },
     Play {
         ply: u8,
@@ -31,53 +83,115 @@ pub enum Command {
         #[arg(short, long, default_value_t = 250)]
         frame_time: u64,
     },
+    /// Plays a move given in UCI (`g1f3`) or standard algebraic notation (`Nf3`)
     Move {
         mv: String,
     },
     Moves {
         sq: Option<String>,
     },
+    /// Writes the moves played since the last `position` or `reset` as PGN, to stdout without a file
+    Save {
+        file: Option<String>,
+    },
     Print,
     Exit,
     Reset,
 }
 
-pub fn handle_command(cmd: Command, board: &mut Board) {
+/// `game` records the moves made with the `move` command, it starts over whenever `board` is replaced
+pub fn handle_command(cmd: Command, board: &mut Board, game: &mut Game, chess960: bool) {
+    board.state.chess960 |= chess960;
     match cmd {
-        Command::Perft { ply, fen, thread_count } => {
+        Command::Perft { ply, fen, thread_count, hash, stats } => {
             if let Some(fen) = fen {
-                *board = match Board::from_fen(&fen) {
+                *board = match load_fen(&fen, chess960) {
                     Ok(b) => b,
                     Err(err) => {
                         println!("Error: {:?}", err);
                         return;
                     }
-                }
+                };
+                *game = Game::new(*board);
             }
-            if let Some(thread_count) = thread_count {
-                board.perft_multithread(ply, thread_count);
+            if stats {
+                print_stats(board, ply);
+            } else if let Some(thread_count) = thread_count {
+                print_multithread(board, ply, thread_count, hash.map(PerftTable::new));
             } else {
-                board.perft(ply);
+                print_divide(board, ply, hash.map(PerftTable::new));
+            }
+        }
+        Command::PerftSuite { file, max_depth, thread_count, hash } => {
+            let table = hash.map(PerftTable::new);
+            let passed = run_perft_suite(&file, max_depth, thread_count, table.as_ref(), chess960);
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Command::Bisect { ply, file, fen } => {
+            if let Some(fen) = fen {
+                *board = match load_fen(&fen, chess960) {
+                    Ok(b) => b,
+                    Err(err) => {
+                        println!("Error: {:?}", err);
+                        return;
+                    }
+                };
+                *game = Game::new(*board);
+            }
+            let Some(file) = file else {
+                print_bisection(board.bisect(ply, &mut ReferenceGenerator));
+                return;
+            };
+            let reference = match std::fs::read_to_string(&file) {
+                Ok(input) => DivideFile::parse(&input),
+                Err(err) => Err(ChessError::Parse(format!("Could not read '{file}': {err}"))),
+            };
+            match reference {
+                Ok(mut reference) => print_bisection(board.bisect(ply, &mut reference)),
+                Err(err) => println!("Error: {:?}", err),
+            }
+        }
+        Command::Fuzz { games, plies, seed } => {
+            let seed = seed.unwrap_or_else(|| {
+                let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+                now.expect("systemtime is fine").as_nanos() as u64
+            });
+            println!("Seed: {seed}");
+            match fuzz(seed, games, plies) {
+                Ok(positions) => println!("Compared {positions} positions"),
+                Err(mismatch) => {
+                    println!("Mismatch in {}", mismatch.fen);
+                    println!("Not generated: {}", mismatch.missing.join(" "));
+                    println!("Generated but illegal: {}", mismatch.extra.join(" "));
+                    std::process::exit(1);
+                }
             }
         }
         Command::Play { ply, frame_time } => {
             board.play(ply, frame_time);
         }
-        Command::Reset => *board = Board::default(),
+        Command::Reset => {
+            *board = Board::default();
+            board.state.chess960 = chess960;
+            *game = Game::new(*board);
+        }
         Command::Print => {
             board.pretty_print(false);
         }
         Command::Position { fen } => {
-            *board = match Board::from_fen(&fen) {
+            *board = match load_fen(&fen, chess960) {
                 Ok(board) => board,
                 Err(err) => {
                     println!("Error: {:?}", err);
                     return;
                 }
-            }
+            };
+            *game = Game::new(*board);
         }
         Command::Move { mv } => {
-            let mv = match Move::from_str(&mv, board) {
+            let mv = match Move::from_str(&mv, board).or_else(|_| board.parse_san(&mv)) {
                 Ok(mv) => mv,
                 Err(err) => {
                     println!("Error: {:?}", err);
@@ -85,7 +199,16 @@ pub fn handle_command(cmd: Command, board: &mut Board) {
                 }
             };
             board.make_move(&mv);
+            game.push(mv);
         }
+        Command::Save { file } => match file {
+            Some(file) => {
+                if let Err(err) = std::fs::write(&file, game.to_pgn()) {
+                    println!("Error: Could not write '{file}': {err}");
+                }
+            }
+            None => print!("{}", game.to_pgn()),
+        },
         Command::Moves { sq } => {
             let moves = match sq {
                 Some(sq) => match Square::from_str(&sq) {
@@ -100,7 +223,7 @@ pub fn handle_command(cmd: Command, board: &mut Board) {
             println!(
                 "Moves: {}",
                 moves.iter().fold(String::new(), |mut acc, mv| {
-                    acc += &mv.to_string();
+                    acc += &board.move_to_string(mv);
                     acc += ", ";
                     acc
                 })
@@ -110,6 +233,142 @@ pub fn handle_command(cmd: Command, board: &mut Board) {
     }
 }
 
+fn print_divide(board: &mut Board, ply: u8, table: Option<PerftTable>) {
+    let start = std::time::Instant::now();
+    let divide = board.divide(ply, table.as_ref());
+    let elapsed = start.elapsed();
+
+    for (mv, count) in &divide {
+        println!("{}: {}", board.move_to_string(mv), count);
+    }
+    let total: u64 = divide.iter().map(|(_, count)| count).sum();
+    println!("Total: {}", total);
+    println!("Million moves/sec: {}", 10f64.powf(-6.0) * total as f64 / elapsed.as_secs_f64());
+}
+
+fn print_multithread(board: &mut Board, ply: u8, thread_count: usize, table: Option<PerftTable>) {
+    let start = std::time::Instant::now();
+    let total = match &table {
+        Some(table) => board.perft_multithread_with_table(ply, thread_count, table),
+        None => board.perft_multithread(ply, thread_count),
+    };
+    let m_moves_per_sec = 10f64.powf(-6.0) * total as f64 / start.elapsed().as_secs_f64();
+    println!("Total: {}", total);
+    println!("Million moves/sec: {}", m_moves_per_sec);
+    println!("Million moves/sec/thread ({thread_count} threads): {}", m_moves_per_sec / thread_count as f64);
+}
+
+fn print_bisection(bisection: Option<Bisection>) {
+    let Some(Bisection { path, fen, depth, mismatch }) = bisection else {
+        println!("All counts match");
+        return;
+    };
+    println!("Moves: {}", path.join(" "));
+    println!("Position: {fen}");
+    match mismatch {
+        Mismatch::Extra(mv) => println!("{mv} is generated but not expected"),
+        Mismatch::Missing(mv) => println!("{mv} is expected but not generated"),
+        Mismatch::Count { mv, expected, found } => println!("{mv}: expected {expected} at depth 1, found {found}"),
+        Mismatch::NoReference => println!("No expected divide, add it under a '{fen} ;D{depth}' line to continue"),
+    }
+}
+
+/// Prints the result of every position and depth, returns whether all of them passed
+fn run_perft_suite(
+    path: &str,
+    max_depth: Option<u8>,
+    thread_count: Option<usize>,
+    table: Option<&PerftTable>,
+    chess960: bool,
+) -> bool {
+    let suite = match std::fs::read_to_string(path) {
+        Ok(input) => PerftSuiteEntry::read_suite(&input),
+        Err(err) => Err(ChessError::Parse(format!("Could not read '{path}': {err}"))),
+    };
+    let suite = match suite {
+        Ok(suite) => suite,
+        Err(err) => {
+            println!("Error: {:?}", err);
+            return false;
+        }
+    };
+
+    let suite_start = std::time::Instant::now();
+    let (mut passed, mut failed, mut total_nodes) = (0, 0, 0);
+    for (i, entry) in suite.iter().enumerate() {
+        println!("#{} {}", i + 1, entry.fen);
+        let mut board = match load_fen(&entry.fen, chess960) {
+            Ok(board) => board,
+            Err(err) => {
+                println!("  Error: {:?}", err);
+                failed += 1;
+                continue;
+            }
+        };
+        for (depth, expected) in &entry.depths {
+            if max_depth.is_some_and(|max_depth| *depth > max_depth) {
+                continue;
+            }
+            let start = std::time::Instant::now();
+            let nodes = match (thread_count, table) {
+                (Some(thread_count), Some(table)) => board.perft_multithread_with_table(*depth, thread_count, table),
+                (Some(thread_count), None) => board.perft_multithread(*depth, thread_count),
+                (None, Some(table)) => board.perft_with_table(*depth, table),
+                (None, None) => board.perft(*depth),
+            };
+            let secs = start.elapsed().as_secs_f64();
+            total_nodes += nodes;
+            let result = if nodes == *expected {
+                passed += 1;
+                "ok".to_string()
+            } else {
+                failed += 1;
+                format!("FAILED, expected {expected}")
+            };
+            println!(
+                "  D{depth} {nodes} {result} ({secs:.3}s, {:.2} million moves/sec)",
+                10f64.powf(-6.0) * nodes as f64 / secs
+            );
+        }
+    }
+
+    let secs = suite_start.elapsed().as_secs_f64();
+    println!(
+        "Passed {passed}, failed {failed} in {secs:.3}s ({:.2} million moves/sec)",
+        10f64.powf(-6.0) * total_nodes as f64 / secs
+    );
+    failed == 0
+}
+
+fn print_stats(board: &mut Board, ply: u8) {
+    println!(
+        "{:>5} {:>12} {:>10} {:>8} {:>8} {:>10} {:>10} {:>10} {:>8} {:>8}",
+        "Depth", "Nodes", "Captures", "E.p.", "Castles", "Promotions", "Checks", "Discovered", "Double", "Mates"
+    );
+    for depth in 1..=ply {
+        let stats = board.perft_stats(depth);
+        println!(
+            "{:>5} {:>12} {:>10} {:>8} {:>8} {:>10} {:>10} {:>10} {:>8} {:>8}",
+            depth,
+            stats.nodes,
+            stats.captures,
+            stats.en_passant,
+            stats.castles,
+            stats.promotions,
+            stats.checks,
+            stats.discovered_checks,
+            stats.double_checks,
+            stats.checkmates
+        );
+    }
+}
+
+fn load_fen(fen: &str, chess960: bool) -> Result<Board, ChessError> {
+    let mut board = Board::from_fen_strict(fen)?;
+    board.state.chess960 |= chess960;
+    Ok(board)
+}
+
 pub fn split_ignore_quotes(input: &str) -> VecDeque<String> {
     let mut split_strings = VecDeque::new();
     let mut inside_quotes = false;
@@ -0,0 +1,40 @@
+use crate::board::Board;
+
+/// Zobrist keys of the positions of a game, the current position last, used to detect repetitions.
+/// Kept out of `Board` so copying a board stays cheap. Whoever makes the moves pushes and pops the keys.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct History {
+    keys: Vec<u64>,
+}
+
+impl History {
+    /// A history holding only the position of `board`
+    pub fn new(board: &Board) -> Self {
+        Self { keys: vec![board.zobrist] }
+    }
+
+    pub fn push(&mut self, key: u64) {
+        self.keys.push(key);
+    }
+
+    pub fn pop(&mut self) {
+        self.keys.pop();
+    }
+
+    /// How many times the current position has occurred, including the current occurrence.
+    /// Only the last `half_move_count` plies are searched since no position before them can be identical.
+    pub fn repetitions(&self, half_move_count: u16) -> usize {
+        let Some((key, earlier)) = self.keys.split_last() else {
+            return 0;
+        };
+        earlier
+            .iter()
+            .rev()
+            .take(half_move_count as usize)
+            .skip(1)
+            .step_by(2)
+            .filter(|k| *k == key)
+            .count()
+            + 1
+    }
+}
@@ -1,10 +1,19 @@
+pub mod bisect;
 pub mod board;
 pub mod movegen;
 pub mod both_colors;
+pub mod builder;
 pub mod cli;
+pub mod history;
+pub mod move_list;
 pub mod mv;
 pub mod parse;
+pub mod perft;
 pub mod piece_bb;
+pub mod reference;
+pub mod see;
 pub mod state;
+pub mod validate;
+pub mod zobrist;
 
-pub use util::{square::{Rank, File, Square}, color::Color, error::ChessError};
+pub use util::{square::{Rank, File, Square}, color::Color, error::ChessError, piece::Piece};
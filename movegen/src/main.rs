@@ -3,12 +3,15 @@ use std::{collections::HashSet, fs, io};
 use movegen::{
     board::Board,
     cli::{handle_command, split_ignore_quotes, Cli, Command},
+    parse::pgn::Game,
 };
 use clap::Parser;
 
 fn main() {
     let args = Cli::parse();
+    let chess960 = args.chess960;
     let mut board = Board::default();
+    let mut game = Game::new(board);
 
     match args.command {
         Command::Interactive => loop {
@@ -23,7 +26,7 @@ fn main() {
             match Cli::try_parse_from(args) {
                 Ok(args) => match args.command {
                     Command::Exit => break,
-                    cmd => handle_command(cmd, &mut board),
+                    cmd => handle_command(cmd, &mut board, &mut game, chess960 || args.chess960),
                 },
                 Err(err) => {
                     err.print()
@@ -31,6 +34,6 @@ fn main() {
                 }
             }
         },
-        cmd => handle_command(cmd, &mut board),
+        cmd => handle_command(cmd, &mut board, &mut game, chess960),
     };
 }
@@ -0,0 +1,154 @@
+use std::{
+    cmp::Reverse,
+    fmt::Debug,
+    ops::{Deref, DerefMut},
+};
+
+use util::{piece::Piece, square::Square};
+
+use crate::mv::{Move, MoveFlag};
+
+/// Stack allocated list of moves, large enough to hold every legal move of any position.
+/// Derefs to a slice, so indexing, iteration and sorting work the same as for a `Vec<Move>`.
+#[derive(Clone, Copy)]
+pub struct MoveList {
+    moves: [Move; Self::CAPACITY],
+    len: usize,
+}
+
+impl MoveList {
+    /// The most legal moves known in a reachable position is 218, so this leaves some headroom
+    pub const CAPACITY: usize = 256;
+
+    const PLACEHOLDER: Move = Move {
+        piece: Piece::Pawn,
+        from: Square::A1,
+        to: Square::A1,
+        flag: MoveFlag::None,
+        promotion: None,
+    };
+
+    pub const fn new() -> Self {
+        Self {
+            moves: [Self::PLACEHOLDER; Self::CAPACITY],
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, mv: Move) {
+        self.moves[self.len] = mv;
+        self.len += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Sorts the moves so that the highest scoring move comes first
+    pub fn sort_by_score<F>(&mut self, mut score: F)
+    where
+        F: FnMut(&Move) -> i32,
+    {
+        self.sort_unstable_by_key(|mv| Reverse(score(mv)));
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for MoveList {
+    type Target = [Move];
+    fn deref(&self) -> &Self::Target {
+        &self.moves[..self.len]
+    }
+}
+
+impl DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl Debug for MoveList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut MoveList {
+    type Item = &'a mut Move;
+    type IntoIter = std::slice::IterMut<'a, Move>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+pub struct IntoIter {
+    list: MoveList,
+    idx: usize,
+}
+
+impl Iterator for IntoIter {
+    type Item = Move;
+    fn next(&mut self) -> Option<Self::Item> {
+        let mv = self.list.get(self.idx).copied();
+        self.idx += 1;
+        mv
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.list.len().saturating_sub(self.idx);
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for IntoIter {}
+
+impl IntoIterator for MoveList {
+    type Item = Move;
+    type IntoIter = IntoIter;
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { list: self, idx: 0 }
+    }
+}
+
+impl FromIterator<Move> for MoveList {
+    fn from_iter<T: IntoIterator<Item = Move>>(iter: T) -> Self {
+        let mut list = Self::new();
+        for mv in iter {
+            list.push(mv);
+        }
+        list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::board::Board;
+
+    #[test]
+    fn matches_generated_moves() {
+        let mut board = Board::default();
+        let mut moves = board.get_moves();
+        assert_eq!(moves.len(), 20);
+
+        let mut generated = Vec::new();
+        board.generate_moves(|_, mv| generated.push(mv.to_string()));
+        let listed: Vec<String> = moves.into_iter().map(|mv| mv.to_string()).collect();
+        assert_eq!(listed, generated);
+
+        moves.sort_by_score(|mv| mv.to as i32);
+        assert!(moves.windows(2).all(|w| w[0].to as u8 >= w[1].to as u8));
+        assert_eq!(moves[0].to.to_string(), "h4");
+    }
+}
@@ -1,8 +1,12 @@
+use std::{fmt::Display, str::FromStr};
+
 use util::{
     piece::Piece,
     square::Square, error::ChessError,
 };
 
+use crate::board::Board;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MoveFlag {
     None,
@@ -35,9 +39,18 @@ impl Promotion {
 
 impl Promotion {
     pub const ALL: [Self; 4] = [Self::Knight, Self::Bishop, Self::Rook, Self::Queen];
+
+    pub fn piece(&self) -> Piece {
+        match self {
+            Self::Knight => Piece::Knight,
+            Self::Bishop => Piece::Bishop,
+            Self::Rook => Piece::Rook,
+            Self::Queen => Piece::Queen,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Move {
     pub piece: Piece,
     pub from: Square,
@@ -48,14 +61,239 @@ pub struct Move {
 
 impl Move {
     pub fn to_string(&self) -> String {
-        let mut s = self.from.to_string() + &self.to.to_string();
+        UciMove::from(self).to_string()
+    }
+
+    pub fn is_castling(&self) -> bool {
+        matches!(self.flag, MoveFlag::KingSideCastles | MoveFlag::QueenSideCastles)
+    }
+}
+
+/// A move in UCI notation like `e7e8q`, parsed without a board. `Board::resolve` turns it into a `Move`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UciMove {
+    pub from: Square,
+    pub to: Square,
+    pub promotion: Option<Promotion>,
+}
+
+impl FromStr for UciMove {
+    type Err = ChessError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        // The fields are sliced by byte, which panics inside a multi-byte character
+        if !input.is_ascii() {
+            return Err(ChessError::Parse(format!("'{input}' is not a move")));
+        }
+        let promotion = match input.len() {
+            4 => None,
+            5 => Some(Promotion::from_str(&input[4..5])?),
+            _ => {
+                return Err(ChessError::Parse(
+                    "Move input should be of form <start><end> eg. 'e2e4'".to_string(),
+                ))
+            }
+        };
+        Ok(Self {
+            from: Square::from_str(&input[0..2])?,
+            to: Square::from_str(&input[2..4])?,
+            promotion,
+        })
+    }
+}
+
+impl Display for UciMove {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.from.to_string(), self.to.to_string())?;
         match self.promotion {
-            Some(Promotion::Knight) => s += "n",
-            Some(Promotion::Bishop) => s += "b",
-            Some(Promotion::Rook) => s += "r",
-            Some(Promotion::Queen) => s += "q",
-            None => {}
+            Some(Promotion::Knight) => write!(f, "n"),
+            Some(Promotion::Bishop) => write!(f, "b"),
+            Some(Promotion::Rook) => write!(f, "r"),
+            Some(Promotion::Queen) => write!(f, "q"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl From<&Move> for UciMove {
+    fn from(mv: &Move) -> Self {
+        Self {
+            from: mv.from,
+            to: mv.to,
+            promotion: mv.promotion,
+        }
+    }
+}
+
+/// A move packed into 16 bits for transposition tables and other compact storage.
+/// Bits 0-5 hold the from square, bits 6-11 the to square and bits 12-15 the flag.
+/// The moving and captured pieces are not stored, so unpacking needs the board the move is played on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PackedMove(pub u16);
+
+impl PackedMove {
+    const QUIET: u16 = 0;
+    const PAWN_FIRST_MOVE: u16 = 1;
+    const KING_SIDE_CASTLES: u16 = 2;
+    const QUEEN_SIDE_CASTLES: u16 = 3;
+    const CAPTURE: u16 = 4;
+    const EN_PASSANT: u16 = 5;
+    const PROMOTION: u16 = 8;
+
+    pub fn from_sq(&self) -> Square {
+        Square::from_u8((self.0 & 0x3F) as u8)
+    }
+
+    pub fn to_sq(&self) -> Square {
+        Square::from_u8(((self.0 >> 6) & 0x3F) as u8)
+    }
+
+    fn flag(&self) -> u16 {
+        self.0 >> 12
+    }
+
+    pub fn promotion(&self) -> Option<Promotion> {
+        if self.flag() & Self::PROMOTION == 0 {
+            None
+        } else {
+            Some(Promotion::ALL[(self.flag() & 0b11) as usize])
+        }
+    }
+
+    /// Restores the full move, looking up the moving and captured pieces on `board`.
+    /// This does not check that the move is legal.
+    pub fn to_move(&self, board: &Board) -> Result<Move, ChessError> {
+        let from = self.from_sq();
+        let to = self.to_sq();
+        let invalid = || ChessError::InvalidMove(format!("{self} does not fit the board"));
+
+        let piece = match board.piece_at(from) {
+            Some((color, piece)) if color == board.state.active_color => piece,
+            _ => return Err(invalid()),
+        };
+        let castles = matches!(self.flag(), Self::KING_SIDE_CASTLES | Self::QUEEN_SIDE_CASTLES);
+        let captured = match board.piece_at(to) {
+            Some((color, piece)) if color != board.state.active_color => Some(piece),
+            // In chess960 the king may castle onto its own rook's square
+            Some(_) if !castles => return Err(invalid()),
+            _ => None,
         };
-        s
+
+        let flag = match (self.flag(), captured) {
+            (Self::QUIET, None) => MoveFlag::None,
+            (Self::PAWN_FIRST_MOVE, None) => MoveFlag::PawnFirstMove,
+            (Self::KING_SIDE_CASTLES, None) => MoveFlag::KingSideCastles,
+            (Self::QUEEN_SIDE_CASTLES, None) => MoveFlag::QueenSideCastles,
+            (Self::EN_PASSANT, None) => MoveFlag::EnPassant,
+            (f, Some(captured)) if f & Self::CAPTURE != 0 && f != Self::EN_PASSANT => {
+                MoveFlag::Capture(captured)
+            }
+            (f, None) if f & (Self::PROMOTION | Self::CAPTURE) == Self::PROMOTION => MoveFlag::None,
+            _ => return Err(invalid()),
+        };
+
+        Ok(Move {
+            piece,
+            from,
+            to,
+            flag,
+            promotion: self.promotion(),
+        })
+    }
+}
+
+impl Display for PackedMove {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.from_sq().to_string(), self.to_sq().to_string())?;
+        match self.promotion() {
+            Some(Promotion::Knight) => write!(f, "n"),
+            Some(Promotion::Bishop) => write!(f, "b"),
+            Some(Promotion::Rook) => write!(f, "r"),
+            Some(Promotion::Queen) => write!(f, "q"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl From<&Move> for PackedMove {
+    fn from(mv: &Move) -> Self {
+        let flag = match (mv.flag, mv.promotion) {
+            (MoveFlag::Capture(_), Some(p)) => Self::PROMOTION | Self::CAPTURE | p as u16,
+            (_, Some(p)) => Self::PROMOTION | p as u16,
+            (MoveFlag::None, None) => Self::QUIET,
+            (MoveFlag::PawnFirstMove, None) => Self::PAWN_FIRST_MOVE,
+            (MoveFlag::KingSideCastles, None) => Self::KING_SIDE_CASTLES,
+            (MoveFlag::QueenSideCastles, None) => Self::QUEEN_SIDE_CASTLES,
+            (MoveFlag::Capture(_), None) => Self::CAPTURE,
+            (MoveFlag::EnPassant, None) => Self::EN_PASSANT,
+        };
+        Self(mv.from as u16 | (mv.to as u16) << 6 | flag << 12)
+    }
+}
+
+impl From<Move> for PackedMove {
+    fn from(mv: Move) -> Self {
+        Self::from(&mv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use util::square::Square;
+
+    use crate::{
+        board::{tests::for_each_node, Board},
+        mv::{Move, MoveFlag, PackedMove, Promotion, UciMove},
+    };
+
+    #[test]
+    fn packed_move_roundtrip() {
+        assert_eq!(std::mem::size_of::<PackedMove>(), 2);
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1",
+        ];
+        for_each_node(&fens, 2, |board| {
+            for mv in board.get_moves().iter() {
+                let packed = PackedMove::from(mv);
+                assert_eq!(packed.to_move(board).ok(), Some(*mv), "{} in {}", mv.to_string(), board.fen(false));
+                assert_eq!(packed.to_string(), mv.to_string());
+            }
+        });
+    }
+
+    #[test]
+    fn packed_move_mismatch() {
+        let board = Board::default();
+        let e2e4 = PackedMove::from(Move::from_str("e2e4", &mut Board::default()).expect("move is legal"));
+        let e7e5 = PackedMove(e2e4.0 & 0xF000 | Square::E7 as u16 | (Square::E5 as u16) << 6);
+        assert!(e2e4.to_move(&board).is_ok());
+        assert!(e7e5.to_move(&board).is_err());
+    }
+
+    #[test]
+    fn uci_move() {
+        let mv: UciMove = "e7e8q".parse().expect("move parses");
+        assert_eq!(mv, UciMove { from: Square::E7, to: Square::E8, promotion: Some(Promotion::Queen) });
+        assert_eq!(mv.to_string(), "e7e8q");
+        for input in ["e7e8k", "e9e8", "e2e", "e2e4e5", "eé4", "e2eé"] {
+            assert!(input.parse::<UciMove>().is_err(), "{input}");
+        }
+
+        // Both ways of writing castling, in chess960 too
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/1K5R w H - 0 1").expect("fen is valid");
+        assert!(board.state.chess960);
+        for input in ["b1g1", "b1h1"] {
+            let mv = board.resolve(input.parse().expect("move parses")).expect("castling is legal");
+            assert_eq!(mv.flag, MoveFlag::KingSideCastles, "{input}");
+        }
+        // unless the king can just step to its destination
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/5K1R w H - 0 1").expect("fen is valid");
+        let mv = board.resolve("f1g1".parse().expect("move parses")).expect("move is legal");
+        assert_eq!(mv.flag, MoveFlag::None);
+        let mv = board.resolve("f1h1".parse().expect("move parses")).expect("castling is legal");
+        assert_eq!(mv.flag, MoveFlag::KingSideCastles);
+        assert!(board.resolve("f1f3".parse().expect("move parses")).is_err());
     }
 }
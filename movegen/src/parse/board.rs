@@ -1,7 +1,6 @@
 use std::{fmt::{Write, format}, io::Empty};
 
 use util::{
-    bitboard::Bitboard,
     color::Color,
     error::ChessError,
     piece::Piece,
@@ -45,9 +44,9 @@ impl Board {
         }
 
         fen += &format!(" {}", self.state.active_color.to_fen());
-        fen += &format!(" {}", self.state.castling.to_fen());
+        fen += &format!(" {}", self.state.castling.to_fen(&self.pieces));
         fen += &format!(" {}", if let Some(ep_file) = self.state.ep_file {
-            Square::ep_move_sq(&self.state.active_color, ep_file).to_string()
+            Square::ep_move_sq(!&self.state.active_color, ep_file).to_string()
         } else {
             "-".to_string()
         });
@@ -60,6 +59,20 @@ impl Board {
 
         fen
     }
+    /// Like `from_fen`, but also rejects positions that fail `validate`
+    pub fn from_fen_strict(input: &str) -> Result<Self, ChessError> {
+        let board = Self::from_fen(input)?;
+        // `from_fen` only keeps the file of the en passant square, so its rank is checked on the input
+        if let Some(sq) = input.split(' ').nth(3).and_then(|ep| Square::from_str(ep).ok()) {
+            if sq != Square::ep_move_sq(!&board.state.active_color, sq.file()) {
+                return Err(ChessError::InvalidEnPassant(sq));
+            }
+        }
+        board.validate()?;
+        Ok(board)
+    }
+
+    /// Parses a FEN without checking that the position could come up in a game, see `from_fen_strict`.
     /// see https://en.wikipedia.org/wiki/Forsyth%E2%80%93Edwards_Notation
     pub fn from_fen(input: &str) -> Result<Self, ChessError> {
         let mut fen_chunks: [&str; 6] = [""; 6];
@@ -85,26 +98,17 @@ impl Board {
             fen_chunks[5] = "1"
         }
 
-        let pieces = match BothColors::from_fen(fen_chunks[0]) {
+        let pieces = match BothColors::<PieceBitboards>::from_fen(fen_chunks[0]) {
             Ok(pieces) => pieces,
             Err(err) => return Err(err),
         };
 
-        let state = match State::from_fen(fen_chunks) {
+        let state = match State::from_fen(fen_chunks, &pieces) {
             Ok(state) => state,
             Err(err) => return Err(err),
         };
 
-        let mut board = Self {
-            pinned: Bitboard::EMPTY,
-            check_masks: [None, None],
-            pieces,
-            state,
-        };
-
-        board.update_slider_checks_pins(&!board.state.active_color);
-
-        Ok(board)
+        Ok(Self::from_parts(pieces, state))
     }
 }
 
@@ -177,7 +181,7 @@ impl BothColors<PieceBitboards> {
 }
 
 impl State {
-    fn from_fen(fen_chunks: [&str; 6]) -> Result<Self, ChessError> {
+    fn from_fen(fen_chunks: [&str; 6], pieces: &BothColors<PieceBitboards>) -> Result<Self, ChessError> {
         let active_color = match fen_chunks[1] {
             "w" => Color::White,
             "b" => Color::Black,
@@ -188,10 +192,7 @@ impl State {
             }
         };
 
-        let castling = match BothColors::from_str(fen_chunks[2]) {
-            Ok(castling) => castling,
-            Err(err) => return Err(err),
-        };
+        let castling = BothColors::<Castling>::from_fen(fen_chunks[2], pieces)?;
 
         let ep_file = match fen_chunks[3] {
             "-" => None,
@@ -227,47 +228,105 @@ impl State {
             ep_file,
             half_move_count,
             full_move_count,
+            chess960: castling.is_chess960(),
         })
     }
 }
 
 impl BothColors<Castling> {
-    fn from_str(input: &str) -> Result<Self, ChessError> {
+    /// Parses standard, X-FEN and Shredder-FEN castling fields.
+    /// `K`/`Q` mean the outermost rook on that side of the king, a file letter names the rook's file directly.
+    fn from_fen(input: &str, pieces: &BothColors<PieceBitboards>) -> Result<Self, ChessError> {
         let mut castling = Self::default();
-        let w = &Color::White;
-        let b = &Color::Black;
-        let err = ChessError::Parse(format!("Castling input '{input}' is invalid"));
-        for i in input.char_indices() {
-            match i {
-                (0, '-') => return Ok(castling),
-                (_, '_') => return Err(err),
-                (_, 'K') => castling[w].king_side = true,
-                (_, 'Q') => castling[w].queen_side = true,
-                (_, 'k') => castling[b].king_side = true,
-                (_, 'q') => castling[b].queen_side = true,
-                _ => return Err(err),
+        if input == "-" {
+            return Ok(castling);
+        }
+        let err = || ChessError::Parse(format!("Castling input '{input}' is invalid"));
+
+        for c in input.chars() {
+            let color = &Color::from_char(c);
+            let rank = Rank::First.pov(color);
+            let king_file = (pieces[color].king & rank.bitboard())
+                .next_sq()
+                .map_or(File::E, |sq| sq.file());
+            let (outermost_king_side, outermost_queen_side) = outermost_rook_files(pieces, color, king_file);
+
+            let castling = &mut castling[color];
+            castling.king_file = king_file;
+            match c.to_ascii_lowercase() {
+                'k' => castling.king_side = Some(outermost_king_side.unwrap_or(File::H)),
+                'q' => castling.queen_side = Some(outermost_queen_side.unwrap_or(File::A)),
+                f => {
+                    let file = File::from_char(f).ok_or_else(err)?;
+                    if file as u8 > king_file as u8 {
+                        castling.king_side = Some(file);
+                    } else if (file as u8) < king_file as u8 {
+                        castling.queen_side = Some(file);
+                    } else {
+                        return Err(err());
+                    }
+                }
             }
         }
         Ok(castling)
     }
-    fn to_fen(&self) -> String {
+
+    /// Writes the castling field as X-FEN, which is the same as standard FEN for standard chess.
+    /// A file letter is only used when there is another rook further out on the same side.
+    fn to_fen(&self, pieces: &BothColors<PieceBitboards>) -> String {
         let mut output = String::new();
-        if self[&Color::White].king_side {
-            output += "K"
-        };
-        if self[&Color::White].king_side {
-            output += "Q"
-        };
-        if self[&Color::White].king_side {
-            output += "k"
-        };
-        if self[&Color::White].king_side {
-            output += "q"
-        };
+        for color in &Color::ALL {
+            let castling = &self[color];
+            let (outermost_king_side, outermost_queen_side) =
+                outermost_rook_files(pieces, color, castling.king_file);
+
+            let sides = [
+                (castling.king_side, 'k', outermost_king_side),
+                (castling.queen_side, 'q', outermost_queen_side),
+            ];
+            for (rook_file, side_char, outermost) in sides {
+                let Some(rook_file) = rook_file else {
+                    continue;
+                };
+                let c = if Some(rook_file) == outermost {
+                    side_char
+                } else {
+                    rook_file.to_char()
+                };
+                output.push(match color {
+                    Color::White => c.to_ascii_uppercase(),
+                    Color::Black => c,
+                });
+            }
+        }
         if output.is_empty() {
             "-".to_string()
         } else {
             output
         }
     }
+
+    /// Whether the rights can only come from a chess960 start position
+    pub(crate) fn is_chess960(&self) -> bool {
+        Color::ALL.iter().any(|color| {
+            let castling = &self[color];
+            (castling.king_side.is_some() || castling.queen_side.is_some())
+                && (castling.king_file != File::E
+                    || castling.king_side.is_some_and(|f| f != File::H)
+                    || castling.queen_side.is_some_and(|f| f != File::A))
+        })
+    }
+}
+
+/// Files of the rooks on the first rank that are furthest from the king on its king side and queen side
+fn outermost_rook_files(
+    pieces: &BothColors<PieceBitboards>,
+    color: &Color,
+    king_file: File,
+) -> (Option<File>, Option<File>) {
+    let rook_files = || (pieces[color].rook & Rank::First.pov(color).bitboard()).map(|sq| sq.file());
+    (
+        rook_files().filter(|f| *f as u8 > king_file as u8).last(),
+        rook_files().find(|f| (*f as u8) < king_file as u8),
+    )
 }
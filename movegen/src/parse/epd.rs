@@ -0,0 +1,275 @@
+use std::str::FromStr;
+
+use util::error::ChessError;
+
+use crate::{board::Board, mv::Move};
+
+/// Opcodes whose operands are moves in SAN, checked against the position when parsing
+const MOVE_OPCODES: [&str; 2] = ["bm", "am"];
+
+/// A position in extended position description, the first four FEN fields followed by operations
+/// like `bm Qg6; id "WAC.001";`. see https://www.chessprogramming.org/Extended_Position_Description
+#[derive(Debug, Clone, PartialEq)]
+pub struct Epd {
+    /// Move counters come from the `hmvc` and `fmvn` opcodes, or from FEN counters in front of the operations
+    pub board: Board,
+    /// Opcodes with their operands in the order they appear, quotes are removed from string operands
+    pub opcodes: Vec<(String, Vec<String>)>,
+}
+
+impl Epd {
+    /// A position without operations
+    pub fn new(board: Board) -> Self {
+        Self { board, opcodes: Vec::new() }
+    }
+
+    /// The operands of the first operation with `opcode`
+    pub fn opcode(&self, opcode: &str) -> Option<&[String]> {
+        self.opcodes.iter().find(|(op, _)| op == opcode).map(|(_, operands)| operands.as_slice())
+    }
+
+    /// Replaces the operands of `opcode`, or adds it at the end
+    pub fn set_opcode(&mut self, opcode: &str, operands: Vec<String>) {
+        match self.opcodes.iter_mut().find(|(op, _)| op == opcode) {
+            Some((_, old)) => *old = operands,
+            None => self.opcodes.push((opcode.to_string(), operands)),
+        }
+    }
+
+    /// The operands of `opcode` read as SAN moves, empty if there is no such operation
+    pub fn moves(&self, opcode: &str) -> Result<Vec<Move>, ChessError> {
+        let mut board = self.board;
+        self.opcode(opcode).unwrap_or_default().iter().map(|san| board.parse_san(san)).collect()
+    }
+
+    /// Sets `opcode` to `moves` written in SAN, eg. `bm` to the best moves
+    pub fn set_moves(&mut self, opcode: &str, moves: &[Move]) {
+        let mut board = self.board;
+        let operands = moves.iter().map(|mv| board.to_san(mv)).collect();
+        self.set_opcode(opcode, operands);
+    }
+
+    /// Writes the position with its operations, each ended by `;`. Move counters other than `0 1` are written
+    /// as `hmvc` and `fmvn` unless those operations are already there.
+    pub fn to_epd(&self) -> String {
+        let state = &self.board.state;
+        let counters: Vec<(String, Vec<String>)> =
+            [("hmvc", state.half_move_count, 0), ("fmvn", state.full_move_count, 1)]
+                .into_iter()
+                .filter(|(opcode, count, default)| count != default && self.opcode(opcode).is_none())
+                .map(|(opcode, count, _)| (opcode.to_string(), vec![count.to_string()]))
+                .collect();
+
+        let mut epd = self.board.epd();
+        for (opcode, operands) in self.opcodes.iter().chain(&counters) {
+            epd += &format!(" {opcode}");
+            for operand in operands {
+                epd.push(' ');
+                if is_string_opcode(opcode) || operand.is_empty() || operand.contains([' ', ';', '"', '\\']) {
+                    epd += &format!("\"{}\"", operand.replace('\\', "\\\\").replace('"', "\\\""));
+                } else {
+                    epd += operand;
+                }
+            }
+            epd.push(';');
+        }
+        epd
+    }
+
+    /// Reads every non-empty line of `input` that does not start with `#`
+    pub fn read_suite(input: &str) -> Result<Vec<Self>, ChessError> {
+        input
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map(|(i, line)| line.parse().map_err(|err| ChessError::Parse(format!("line {}: {err:?}", i + 1))))
+            .collect()
+    }
+}
+
+/// `id` and the comments `c0` to `c9` are always quoted
+fn is_string_opcode(opcode: &str) -> bool {
+    opcode == "id" || (opcode.len() == 2 && opcode.starts_with('c') && opcode.ends_with(|c: char| c.is_ascii_digit()))
+}
+
+/// Splits `input` at the next space or `;`
+fn next_field(input: &str) -> (&str, &str) {
+    let end = input.find(|c: char| c.is_whitespace() || c == ';').unwrap_or(input.len());
+    (&input[..end], input[end..].trim_start())
+}
+
+/// Reads the operations after the position. The `;` after the last one may be missing.
+fn parse_operations(input: &str) -> Result<Vec<(String, Vec<String>)>, ChessError> {
+    let mut operations = Vec::new();
+    let mut tokens = Vec::new();
+    let mut chars = input.chars();
+    loop {
+        let c = chars.next();
+        match c {
+            Some(c) if c.is_whitespace() => continue,
+            Some('"') => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => string.extend(chars.next()),
+                        Some(c) => string.push(c),
+                        None => return Err(ChessError::Parse(format!("String \"{string} is not closed"))),
+                    }
+                }
+                tokens.push(string);
+                continue;
+            }
+            Some(c) if c != ';' => {
+                let mut token = c.to_string();
+                let rest = chars.as_str();
+                let end = rest.find(|c: char| c.is_whitespace() || c == ';' || c == '"').unwrap_or(rest.len());
+                token += &rest[..end];
+                chars = rest[end..].chars();
+                tokens.push(token);
+                continue;
+            }
+            _ => {}
+        }
+
+        // the end of an operation
+        if !tokens.is_empty() {
+            let opcode = tokens.remove(0);
+            if !opcode.starts_with(|c: char| c.is_ascii_alphabetic())
+                || !opcode.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+                return Err(ChessError::Parse(format!("'{opcode}' is not an opcode")));
+            }
+            operations.push((opcode, std::mem::take(&mut tokens)));
+        }
+        if c.is_none() {
+            return Ok(operations);
+        }
+    }
+}
+
+impl FromStr for Epd {
+    type Err = ChessError;
+
+    /// Also takes the half and full move counters of a FEN after the four fields, as in perft suites
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut rest = input.trim();
+        let mut fields = Vec::new();
+        for _ in 0..4 {
+            let (field, next) = next_field(rest);
+            if field.is_empty() {
+                return Err(ChessError::Parse(format!("Too few EPD fields in '{input}'")));
+            }
+            fields.push(field);
+            rest = next;
+        }
+        let mut counters = Vec::new();
+        while counters.len() < 2 {
+            let (counter, next) = next_field(rest);
+            if counter.is_empty() || !counter.chars().all(|c| c.is_ascii_digit()) {
+                break;
+            }
+            counters.push(counter);
+            rest = next;
+        }
+
+        let opcodes = parse_operations(rest)?;
+        let counter = |i: usize, opcode: &str, default| {
+            match opcodes.iter().find(|(op, _)| op == opcode).map(|(_, operands)| operands.as_slice()) {
+                Some([value]) => Ok(value.as_str()),
+                Some(_) => Err(ChessError::Parse(format!("'{opcode}' needs a single operand"))),
+                None => Ok(counters.get(i).copied().unwrap_or(default)),
+            }
+        };
+        let fen = format!("{} {} {}", fields.join(" "), counter(0, "hmvc", "0")?, counter(1, "fmvn", "1")?);
+        let epd = Self { board: Board::from_fen_strict(&fen)?, opcodes };
+
+        for opcode in MOVE_OPCODES {
+            epd.moves(opcode)?;
+        }
+        Ok(epd)
+    }
+}
+
+impl Board {
+    /// The first four fields of `fen`, without move counters
+    pub fn epd(&self) -> String {
+        self.fen(false).split(' ').take(4).collect::<Vec<_>>().join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use util::error::ChessError;
+
+    use crate::{board::Board, mv::Move, parse::epd::Epd};
+
+    #[test]
+    fn read_epd() {
+        let epd: Epd = "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";"
+            .parse()
+            .expect("epd is valid");
+        assert_eq!(epd.board.fen(false), "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1");
+        assert_eq!(epd.opcode("id"), Some(["WAC.001".to_string()].as_slice()));
+        let mut board = epd.board;
+        assert_eq!(epd.moves("bm"), Ok(vec![Move::from_str("g3g6", &mut board).expect("move is legal")]));
+        assert_eq!(epd.moves("am"), Ok(Vec::new()));
+
+        let epd: Epd = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - hmvc 12; fmvn 40; am O-O O-O-O; c0 \"a; \\\"b\\\"\"; acd 8"
+            .parse()
+            .expect("epd is valid");
+        assert_eq!(epd.board.fen(false), "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 12 40");
+        assert_eq!(epd.moves("am").map(|moves| moves.len()), Ok(2));
+        assert_eq!(epd.opcode("c0"), Some(["a; \"b\"".to_string()].as_slice()));
+        assert_eq!(epd.opcode("acd"), Some(["8".to_string()].as_slice()));
+
+        let epd: Epd =
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 7 ;D1 20 ;D2 400".parse().expect("epd is valid");
+        assert_eq!(epd.board.state.full_move_count, 7);
+        assert_eq!(epd.opcode("D2"), Some(["400".to_string()].as_slice()));
+
+        for input in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e5;",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - id \"open;",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 1x 2;",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - hmvc 1 2;",
+            "8/8/8/8/8/8/8/8 w - - bm e4;",
+        ] {
+            let err = input.parse::<Epd>().expect_err(input);
+            assert!(
+                matches!(err, ChessError::Parse(_) | ChessError::InvalidMove(_) | ChessError::MissingKing(_)),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn write_epd() {
+        let mut board = Board::from_fen("r3k2r/1P6/8/8/8/8/8/R3K1NR w KQkq - 3 20").expect("fen is valid");
+        assert_eq!(board.epd(), "r3k2r/1P6/8/8/8/8/8/R3K1NR w KQkq -");
+
+        let mut epd = Epd::new(board);
+        let moves = ["b7a8q", "e1c1"].map(|mv| Move::from_str(mv, &mut board).expect("move is legal"));
+        epd.set_moves("bm", &moves);
+        epd.set_opcode("id", vec!["promotion".to_string()]);
+        epd.set_opcode("c0", vec!["say \"hi\"".to_string(), String::new()]);
+        let written = epd.to_epd();
+        assert_eq!(
+            written,
+            "r3k2r/1P6/8/8/8/8/8/R3K1NR w KQkq - bm bxa8=Q+ O-O-O; id \"promotion\"; c0 \"say \\\"hi\\\"\" \"\"; hmvc 3; fmvn 20;"
+        );
+
+        let read: Epd = written.parse().expect("written epd is valid");
+        assert_eq!(read.opcodes[..epd.opcodes.len()], epd.opcodes);
+        assert_eq!(read.moves("bm"), Ok(moves.to_vec()));
+        assert_eq!(read.board.state, epd.board.state);
+        assert_eq!(read.to_epd(), written);
+
+        // counters of a FEN are kept
+        let epd: Epd = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 7 ;D1 20".parse().expect("epd is valid");
+        let written = epd.to_epd();
+        assert_eq!(written, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - D1 20; fmvn 7;");
+        assert_eq!(written.parse::<Epd>().map(|read| read.board.state.full_move_count), Ok(7));
+    }
+}
@@ -1,2 +1,5 @@
 pub mod board;
+pub mod epd;
 mod mv;
+pub mod pgn;
+mod san;
@@ -1,42 +1,54 @@
-use util::{error::ChessError, square::Square};
+use util::{
+    error::ChessError,
+    square::{Rank, Square},
+};
 
 use crate::{
     board::Board,
-    mv::{Move, Promotion},
+    mv::{Move, MoveFlag, UciMove},
 };
 
 impl Move {
     pub fn from_str(input: &str, board: &mut Board) -> Result<Self, ChessError> {
-        let promotion = match input.len() {
-            4 => None,
-            5 => Some(match Promotion::from_str(&input[4..5]) {
-                Ok(p) => p,
-                Err(err) => return Err(err),
-            }),
-            _ => {
-                return Err(ChessError::Parse(format!(
-                    "Move input should be of form <start><end> eg. 'e2e4'"
-                )))
-            }
-        };
+        board.resolve(input.parse()?)
+    }
+}
 
-        let piece_sq = match Square::from_str(&input[0..2]) {
-            Ok(p) => p,
-            Err(e) => return Err(e),
-        };
-        let to = match Square::from_str(&input[2..4]) {
-            Ok(to) => to,
-            Err(err) => return Err(err),
+impl Board {
+    /// Finds the legal move `mv` stands for. Castling can be given as the king capturing its own rook
+    /// or as the king moving to its destination, unless that is also a normal king move as can happen in chess960.
+    pub fn resolve(&mut self, mv: UciMove) -> Result<Move, ChessError> {
+        let moves = self.get_sq_moves(mv.from);
+        moves
+            .iter()
+            .find(|m| !m.is_castling() && m.to == mv.to && m.promotion == mv.promotion)
+            .or_else(|| {
+                moves.iter().find(|m| {
+                    m.is_castling()
+                        && mv.promotion.is_none()
+                        && (Some(mv.to) == self.castling_rook_sq(m) || mv.to == m.to)
+                })
+            })
+            .copied()
+            .ok_or_else(|| ChessError::InvalidMove(format!("{mv} is not a valid move")))
+    }
+
+    /// Start square of the rook that castles with `mv`, if `mv` is a castling move of this position
+    pub fn castling_rook_sq(&self, mv: &Move) -> Option<Square> {
+        let castling = &self.state.castling[&self.state.active_color];
+        let rook_file = match mv.flag {
+            MoveFlag::KingSideCastles => castling.king_side,
+            MoveFlag::QueenSideCastles => castling.queen_side,
+            _ => None,
         };
+        rook_file.map(|file| Square::from_rank_file(Rank::First.pov(&self.state.active_color), file))
+    }
 
-        for mv in board.get_sq_moves(piece_sq) {
-            if mv.to == to && promotion == mv.promotion {
-                return Ok(mv);
-            }
+    /// Writes `mv` in UCI notation. In chess960 castling is written as the king capturing its own rook.
+    pub fn move_to_string(&self, mv: &Move) -> String {
+        match self.castling_rook_sq(mv) {
+            Some(rook_sq) if self.state.chess960 => mv.from.to_string() + &rook_sq.to_string(),
+            _ => mv.to_string(),
         }
-
-        Err(ChessError::InvalidMove(format!(
-            "{input} is not a valid move",
-        )))
     }
 }
@@ -0,0 +1,818 @@
+use std::io::BufRead;
+
+use util::{color::Color, error::ChessError};
+
+use crate::{board::Board, mv::Move};
+
+/// The tags every PGN game should have, in the order they are exported
+pub const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+/// Exported lines are at most this long
+const MAX_LINE_LEN: usize = 79;
+
+/// A move of a game with the annotations that follow it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub mv: Move,
+    /// Numeric annotation glyphs, the suffixes `!`, `?`, `!!`, `??`, `!?` and `?!` are stored as 1 to 6
+    pub nags: Vec<u8>,
+    /// Comments in front of the first move of a variation
+    pub comments_before: Vec<String>,
+    pub comments: Vec<String>,
+    /// Commands embedded in the comments like `[%clk 0:03:00]`, as name and arguments
+    pub commands: Vec<(String, String)>,
+    pub parent: Option<usize>,
+    /// Moves played from the position after this one, the main continuation first and then the variations
+    pub children: Vec<usize>,
+}
+
+/// A game read from PGN. The moves form a tree stored in `nodes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Game {
+    /// In the order they appear
+    pub tags: Vec<(String, String)>,
+    /// Position before the first move, set up from the `FEN` tag if there is one
+    pub start: Board,
+    /// Comments in front of the first move
+    pub comments: Vec<String>,
+    pub nodes: Vec<Node>,
+    /// The first moves, the main line first
+    pub children: Vec<usize>,
+    /// `1-0`, `0-1`, `1/2-1/2` or `*`
+    pub result: String,
+}
+
+impl Game {
+    /// A game without moves from `start`, with the seven tag roster filled with unknown values
+    pub fn new(start: Board) -> Self {
+        let tags = SEVEN_TAG_ROSTER
+            .iter()
+            .map(|tag| {
+                let value = match *tag {
+                    "Date" => "????.??.??",
+                    "Result" => "*",
+                    _ => "?",
+                };
+                (tag.to_string(), value.to_string())
+            })
+            .collect();
+        Self {
+            tags,
+            start,
+            comments: Vec::new(),
+            nodes: Vec::new(),
+            children: Vec::new(),
+            result: "*".to_string(),
+        }
+    }
+
+    /// Adds `mv` after `parent`, or as a first move. It becomes the main continuation if there is none yet.
+    /// The move is not checked to be legal.
+    pub fn add_move(&mut self, parent: Option<usize>, mv: Move) -> usize {
+        let idx = self.nodes.len();
+        self.nodes.push(Node {
+            mv,
+            nags: Vec::new(),
+            comments_before: Vec::new(),
+            comments: Vec::new(),
+            commands: Vec::new(),
+            parent,
+            children: Vec::new(),
+        });
+        match parent {
+            Some(parent) => self.nodes[parent].children.push(idx),
+            None => self.children.push(idx),
+        }
+        idx
+    }
+
+    /// Adds `mv` at the end of the main line
+    pub fn push(&mut self, mv: Move) -> usize {
+        let mut last = None;
+        while let Some(next) = last.map_or(&self.children, |i: usize| &self.nodes[i].children).first() {
+            last = Some(*next);
+        }
+        self.add_move(last, mv)
+    }
+
+    /// Reads the first game of `input`
+    pub fn from_pgn(input: &str) -> Result<Self, ChessError> {
+        PgnReader::new(input.as_bytes())
+            .next()
+            .unwrap_or_else(|| Err(ChessError::Parse("No game found".to_string())))
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    /// The moves of the main line
+    pub fn mainline(&self) -> impl Iterator<Item = &Node> {
+        let mut next = self.children.first();
+        std::iter::from_fn(move || {
+            let node = &self.nodes[*next?];
+            next = node.children.first();
+            Some(node)
+        })
+    }
+
+    /// Every move with the position it is played in. The whole main line comes first,
+    /// then the variations, each of them again main line first.
+    pub fn iter(&self) -> GameIter<'_> {
+        GameIter {
+            game: self,
+            stack: self.children.iter().rev().map(|child| (*child, self.start)).collect(),
+        }
+    }
+}
+
+pub struct GameIter<'a> {
+    game: &'a Game,
+    /// Moves still to visit with the position before them, the next one last
+    stack: Vec<(usize, Board)>,
+}
+
+impl<'a> Iterator for GameIter<'a> {
+    type Item = (Board, &'a Node);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (idx, board) = self.stack.pop()?;
+        let node = &self.game.nodes[idx];
+        let mut after = board;
+        after.make_move(&node.mv);
+        self.stack.extend(node.children.iter().rev().map(|child| (*child, after)));
+        Some((board, node))
+    }
+}
+
+impl Game {
+    /// Writes the game in PGN export format. The seven tag roster comes first and `SetUp` and `FEN` tags
+    /// are added if the game does not start from the standard position.
+    pub fn to_pgn(&self) -> String {
+        let mut tags: Vec<(&str, &str)> = SEVEN_TAG_ROSTER
+            .iter()
+            .map(|name| match *name {
+                "Result" => (*name, self.result.as_str()),
+                "Date" => (*name, self.tag(name).unwrap_or("????.??.??")),
+                _ => (*name, self.tag(name).unwrap_or("?")),
+            })
+            .collect();
+        let fen = self.start.fen(false);
+        if fen != Board::default().fen(false) {
+            tags.extend([("SetUp", "1"), ("FEN", fen.as_str())]);
+        }
+        if self.start.state.chess960 && self.tag("Variant").is_none() {
+            tags.push(("Variant", "Chess960"));
+        }
+        tags.extend(
+            self.tags
+                .iter()
+                .filter(|(name, _)| !SEVEN_TAG_ROSTER.contains(&name.as_str()) && name != "SetUp" && name != "FEN")
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        );
+
+        let mut pgn = String::new();
+        for (name, value) in tags {
+            pgn += &format!("[{name} \"{}\"]\n", value.replace('\\', "\\\\").replace('"', "\\\""));
+        }
+        pgn.push('\n');
+
+        let mut tokens: Vec<String> = self.comments.iter().map(|comment| comment_token(comment)).collect();
+        if let Some((first, alternatives)) = self.children.split_first() {
+            self.write_moves(&mut tokens, self.start, *first, alternatives);
+        }
+        tokens.push(self.result.clone());
+
+        // Parentheses stick to the token next to them, comments may be broken up between their words.
+        // A parenthesis inside a comment is part of its own token and stays where it is.
+        let mut words: Vec<String> = Vec::new();
+        let mut after_paren = false;
+        for token in &tokens {
+            for (i, word) in token.split_whitespace().enumerate() {
+                match words.last_mut() {
+                    Some(last) if (i == 0 && after_paren) || token == ")" => *last += word,
+                    _ => words.push(word.to_string()),
+                }
+            }
+            after_paren = token == "(";
+        }
+        let mut line = String::new();
+        for word in words {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > MAX_LINE_LEN {
+                pgn += &line;
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line += &word;
+        }
+        pgn += &line;
+        pgn.push('\n');
+        pgn
+    }
+
+    /// Writes the line starting with `idx` and the `alternatives` to it, each in parentheses after the move they replace.
+    /// Black moves get a move number with `...` at the start of a line and after a comment or variation.
+    fn write_moves<'a>(
+        &'a self,
+        tokens: &mut Vec<String>,
+        mut board: Board,
+        mut idx: usize,
+        mut alternatives: &'a [usize],
+    ) {
+        let mut number_black = true;
+        loop {
+            let node = &self.nodes[idx];
+            for comment in &node.comments_before {
+                tokens.push(comment_token(comment));
+                number_black = true;
+            }
+            let number = board.state.full_move_count;
+            match board.state.active_color {
+                Color::White => tokens.push(format!("{number}.")),
+                Color::Black if number_black => tokens.push(format!("{number}...")),
+                Color::Black => {}
+            }
+            tokens.push(board.to_san(&node.mv));
+            tokens.extend(node.nags.iter().map(|nag| format!("${nag}")));
+
+            let commands = node.commands.iter().map(|(name, args)| match args.is_empty() {
+                true => format!("[%{name}]"),
+                false => format!("[%{name} {args}]"),
+            });
+            let mut comments = node.comments.clone();
+            if !node.commands.is_empty() {
+                let commands = commands.collect::<Vec<_>>().join(" ");
+                match comments.first_mut() {
+                    Some(first) => *first = format!("{commands} {first}"),
+                    None => comments.push(commands),
+                }
+            }
+            number_black = !comments.is_empty() || !alternatives.is_empty();
+            tokens.extend(comments.iter().map(|comment| comment_token(comment)));
+
+            for alternative in alternatives {
+                tokens.push("(".to_string());
+                self.write_moves(tokens, board, *alternative, &[]);
+                tokens.push(")".to_string());
+            }
+
+            let Some((next, next_alternatives)) = node.children.split_first() else {
+                return;
+            };
+            board.make_move(&node.mv);
+            idx = *next;
+            alternatives = next_alternatives;
+        }
+    }
+}
+
+/// `comment` in braces, without the `}` that would end it early
+fn comment_token(comment: &str) -> String {
+    format!("{{{}}}", comment.replace('}', ""))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    TagStart,
+    TagEnd,
+    String(String),
+    Comment(String),
+    VariationStart,
+    VariationEnd,
+    Nag(u8),
+    /// Moves and tag names
+    Symbol(String),
+    Result(String),
+}
+
+/// Line and column, both starting at 1
+type Pos = (usize, usize);
+
+fn error((line, column): Pos, msg: &str) -> ChessError {
+    ChessError::Parse(format!("line {line}, column {column}: {msg}"))
+}
+
+/// Splits a comment into its text and the commands embedded in it
+fn split_commands(comment: &str) -> (String, Vec<(String, String)>) {
+    let mut text = String::new();
+    let mut commands = Vec::new();
+    let mut rest = comment;
+    while let Some(start) = rest.find("[%") {
+        let Some(len) = rest[start..].find(']') else {
+            break;
+        };
+        text += &rest[..start];
+        let command = rest[start + 2..start + len].trim();
+        let (name, args) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+        commands.push((name.to_string(), args.trim().to_string()));
+        rest = &rest[start + len + 1..];
+    }
+    text += rest;
+    (text.split_whitespace().collect::<Vec<_>>().join(" "), commands)
+}
+
+/// Hands out the characters of a reader one line at a time and turns them into tokens
+struct Lexer<R> {
+    reader: R,
+    line: Vec<char>,
+    line_no: usize,
+    col: usize,
+}
+
+impl<R: BufRead> Lexer<R> {
+    /// Returns false at the end of the input. Lines starting with `%` are skipped.
+    fn next_line(&mut self) -> Result<bool, ChessError> {
+        let mut buf = String::new();
+        let read = self.reader.read_line(&mut buf).map_err(|err| error(self.pos(), &err.to_string()))?;
+        if read == 0 {
+            return Ok(false);
+        }
+        self.line_no += 1;
+        self.col = 0;
+        self.line = if buf.starts_with('%') { vec!['\n'] } else { buf.chars().collect() };
+        Ok(true)
+    }
+
+    fn peek(&mut self) -> Result<Option<char>, ChessError> {
+        while self.col >= self.line.len() {
+            if !self.next_line()? {
+                return Ok(None);
+            }
+        }
+        Ok(Some(self.line[self.col]))
+    }
+
+    fn pos(&self) -> Pos {
+        (self.line_no, self.col + 1)
+    }
+
+    /// Takes characters for as long as `pred` holds, within the current line
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> String {
+        let start = self.col;
+        while self.col < self.line.len() && pred(self.line[self.col]) {
+            self.col += 1;
+        }
+        self.line[start..self.col].iter().collect()
+    }
+
+    fn token(&mut self) -> Result<Option<(Token, Pos)>, ChessError> {
+        loop {
+            let Some(c) = self.peek()? else {
+                return Ok(None);
+            };
+            let pos = self.pos();
+            if c.is_whitespace() || c == '.' {
+                self.col += 1;
+                continue;
+            }
+
+            let token = match c {
+                '[' | ']' | '(' | ')' | '*' => {
+                    self.col += 1;
+                    match c {
+                        '[' => Token::TagStart,
+                        ']' => Token::TagEnd,
+                        '(' => Token::VariationStart,
+                        ')' => Token::VariationEnd,
+                        _ => Token::Result("*".to_string()),
+                    }
+                }
+                '"' => {
+                    self.col += 1;
+                    let mut value = String::new();
+                    loop {
+                        match self.line.get(self.col) {
+                            Some('"') => break,
+                            Some('\\') if matches!(self.line.get(self.col + 1), Some('"' | '\\')) => {
+                                value.push(self.line[self.col + 1]);
+                                self.col += 1;
+                            }
+                            Some('\n') | None => return Err(error(pos, "Unterminated string")),
+                            Some(c) => value.push(*c),
+                        }
+                        self.col += 1;
+                    }
+                    self.col += 1;
+                    Token::String(value)
+                }
+                '{' => {
+                    self.col += 1;
+                    let mut comment = String::new();
+                    loop {
+                        match self.peek()? {
+                            Some('}') => break,
+                            Some(c) => comment.push(c),
+                            None => return Err(error(pos, "Unterminated comment")),
+                        }
+                        self.col += 1;
+                    }
+                    self.col += 1;
+                    Token::Comment(comment.trim().to_string())
+                }
+                ';' => {
+                    self.col += 1;
+                    Token::Comment(self.take_while(|c| c != '\n').trim().to_string())
+                }
+                '$' => {
+                    self.col += 1;
+                    let nag = self.take_while(|c| c.is_ascii_digit());
+                    Token::Nag(nag.parse().map_err(|_| error(pos, &format!("'${nag}' is not a NAG")))?)
+                }
+                '!' | '?' => {
+                    let suffix = self.take_while(|c| c == '!' || c == '?');
+                    let nag = ["!", "?", "!!", "??", "!?", "?!"].iter().position(|s| *s == suffix);
+                    Token::Nag(nag.ok_or_else(|| error(pos, &format!("'{suffix}' is not an annotation")))? as u8 + 1)
+                }
+                c if c.is_alphanumeric() || ('♔'..='♟').contains(&c) => {
+                    let symbol = self.take_while(|c| {
+                        c.is_alphanumeric() || ('♔'..='♟').contains(&c) || "_+#=:-/".contains(c)
+                    });
+                    match symbol.as_str() {
+                        "1-0" | "0-1" | "1/2-1/2" => Token::Result(symbol),
+                        // Move numbers carry no information, the dots are skipped with the whitespace
+                        _ if symbol.chars().all(|c| c.is_ascii_digit()) && self.line.get(self.col) == Some(&'.') => {
+                            continue
+                        }
+                        _ => Token::Symbol(symbol),
+                    }
+                }
+                c => return Err(error(pos, &format!("Unexpected '{c}'"))),
+            };
+            return Ok(Some((token, pos)));
+        }
+    }
+
+    /// Skips to the next line starting with `[` after an empty line, which should be the next game's tags
+    fn skip_game(&mut self) {
+        self.col = self.line.len();
+        let mut blank = false;
+        while let Ok(true) = self.next_line() {
+            blank |= self.line.iter().all(|c| c.is_whitespace());
+            if blank && self.line.first() == Some(&'[') {
+                return;
+            }
+        }
+    }
+}
+
+/// The moves of the variation being read
+struct Variation {
+    /// Move the variation branches off from
+    parent: Option<usize>,
+    last: Option<usize>,
+    board: Board,
+    /// Position before `last`, where a variation of `last` starts
+    before_last: Board,
+    /// Comments in front of the first move
+    comments: Vec<String>,
+}
+
+/// Reads the games of a PGN file one at a time. After an error the rest of that game is skipped.
+pub struct PgnReader<R> {
+    lexer: Lexer<R>,
+    peeked: Option<(Token, Pos)>,
+    failed: bool,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lexer: Lexer {
+                reader,
+                line: Vec::new(),
+                line_no: 0,
+                col: 0,
+            },
+            peeked: None,
+            failed: false,
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<(Token, Pos)>, ChessError> {
+        match self.peeked.take() {
+            Some(token) => Ok(Some(token)),
+            None => self.lexer.token(),
+        }
+    }
+
+    fn expected(&self, token: Option<(Token, Pos)>, expected: &str) -> ChessError {
+        let pos = token.map_or(self.lexer.pos(), |(_, pos)| pos);
+        error(pos, &format!("Expected {expected}"))
+    }
+
+    fn read_tags(&mut self) -> Result<Vec<(String, String, Pos)>, ChessError> {
+        let mut tags = Vec::new();
+        loop {
+            match self.next_token()? {
+                Some((Token::TagStart, _)) => {
+                    let name = match self.next_token()? {
+                        Some((Token::Symbol(name), _)) => name,
+                        token => return Err(self.expected(token, "a tag name")),
+                    };
+                    let (value, pos) = match self.next_token()? {
+                        Some((Token::String(value), pos)) => (value, pos),
+                        token => return Err(self.expected(token, "a tag value")),
+                    };
+                    match self.next_token()? {
+                        Some((Token::TagEnd, _)) => tags.push((name, value, pos)),
+                        token => return Err(self.expected(token, "']'")),
+                    }
+                }
+                token => {
+                    self.peeked = token;
+                    return Ok(tags);
+                }
+            }
+        }
+    }
+
+    fn read_game(&mut self) -> Result<Option<Game>, ChessError> {
+        let tags = self.read_tags()?;
+        if tags.is_empty() && self.peeked.is_none() {
+            return Ok(None);
+        }
+
+        let mut start = Board::default();
+        for (name, value, pos) in &tags {
+            match name.as_str() {
+                "FEN" => start = Board::from_fen_strict(value).map_err(|err| error(*pos, &format!("{err:?}")))?,
+                "Variant" if value.contains("960") || value.eq_ignore_ascii_case("fischerandom") => {
+                    start.state.chess960 = true
+                }
+                _ => {}
+            }
+        }
+        let mut game = Game {
+            tags: tags.into_iter().map(|(name, value, _)| (name, value)).collect(),
+            start,
+            comments: Vec::new(),
+            nodes: Vec::new(),
+            children: Vec::new(),
+            result: "*".to_string(),
+        };
+
+        let mut variations = vec![Variation {
+            parent: None,
+            last: None,
+            board: start,
+            before_last: start,
+            comments: Vec::new(),
+        }];
+        loop {
+            let Some((token, pos)) = self.next_token()? else {
+                if variations.len() > 1 {
+                    return Err(error(self.lexer.pos(), "Unclosed variation"));
+                }
+                break;
+            };
+            let depth = variations.len();
+            let variation = variations.last_mut().expect("the main line is never closed");
+            match token {
+                Token::Symbol(san) => {
+                    let mv = variation.board.parse_san(&san).map_err(|err| error(pos, &format!("{err:?}")))?;
+                    let idx = game.nodes.len();
+                    let parent = variation.last.or(variation.parent);
+                    let mut node = Node {
+                        mv,
+                        nags: Vec::new(),
+                        comments_before: Vec::new(),
+                        comments: Vec::new(),
+                        commands: Vec::new(),
+                        parent,
+                        children: Vec::new(),
+                    };
+                    for comment in variation.comments.drain(..) {
+                        let (text, commands) = split_commands(&comment);
+                        node.comments_before.extend(Some(text).filter(|text| !text.is_empty()));
+                        node.commands.extend(commands);
+                    }
+                    game.nodes.push(node);
+                    match parent {
+                        Some(parent) => game.nodes[parent].children.push(idx),
+                        None => game.children.push(idx),
+                    }
+                    variation.before_last = variation.board;
+                    variation.board.make_move(&mv);
+                    variation.last = Some(idx);
+                }
+                Token::Nag(nag) => {
+                    let last = variation.last.ok_or_else(|| error(pos, "Annotation before the first move"))?;
+                    game.nodes[last].nags.push(nag);
+                }
+                Token::Comment(comment) => match variation.last {
+                    Some(last) => {
+                        let (text, commands) = split_commands(&comment);
+                        game.nodes[last].comments.extend(Some(text).filter(|text| !text.is_empty()));
+                        game.nodes[last].commands.extend(commands);
+                    }
+                    None if depth == 1 => game.comments.push(comment),
+                    None => variation.comments.push(comment),
+                },
+                Token::VariationStart => {
+                    let last = variation.last.ok_or_else(|| error(pos, "Variation before the first move"))?;
+                    let board = variation.before_last;
+                    variations.push(Variation {
+                        parent: game.nodes[last].parent,
+                        last: None,
+                        board,
+                        before_last: board,
+                        comments: Vec::new(),
+                    });
+                }
+                Token::VariationEnd => {
+                    if variations.len() == 1 {
+                        return Err(error(pos, "Unmatched ')'"));
+                    }
+                    variations.pop();
+                }
+                Token::Result(result) => {
+                    if variations.len() > 1 {
+                        return Err(error(pos, "Result inside a variation"));
+                    }
+                    game.result = result;
+                    break;
+                }
+                // The next game starts without this one having a result
+                Token::TagStart if variations.len() == 1 => {
+                    self.peeked = Some((token, pos));
+                    break;
+                }
+                Token::TagStart | Token::TagEnd | Token::String(_) => {
+                    return Err(error(pos, "Unexpected token in the moves"));
+                }
+            }
+        }
+        Ok(Some(game))
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<Game, ChessError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            self.failed = false;
+            self.peeked = None;
+            self.lexer.skip_game();
+        }
+        match self.read_game() {
+            Ok(game) => game.map(Ok),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use util::error::ChessError;
+
+    use crate::{
+        board::Board,
+        mv::Move,
+        parse::pgn::{Game, Node, PgnReader},
+    };
+
+    const GAME: &str = r#"[Event "Casual \"blitz\""]
+[Site "?"]
+[Date "2024.01.01"]
+[Round "1"]
+[White "A"]
+[Black "B"]
+[Result "1-0"]
+[WhiteElo "2000"]
+
+{Opening comment} 1. e4 {[%clk 0:05:00] best by test} e5 $1 (1... c5 2. Nf3 (2. c3) d6)
+2. Nf3!? Nc6 ; rest of the line
+% escaped line
+3.Bb5 a6 1-0
+"#;
+
+    fn ucis<'a>(nodes: impl Iterator<Item = &'a Node>) -> Vec<String> {
+        nodes.map(|node| node.mv.to_string()).collect()
+    }
+
+    #[test]
+    fn read_game() {
+        let game = Game::from_pgn(GAME).expect("pgn is valid");
+        assert_eq!(game.tags.len(), 8);
+        assert_eq!(game.tag("Event"), Some("Casual \"blitz\""));
+        assert_eq!(game.tag("WhiteElo"), Some("2000"));
+        assert_eq!(game.comments, ["Opening comment"]);
+        assert_eq!(game.result, "1-0");
+        assert_eq!(ucis(game.mainline()), ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6"]);
+        assert_eq!(
+            ucis(game.iter().map(|(_, node)| node)),
+            ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "c7c5", "g1f3", "d7d6", "c2c3"]
+        );
+
+        let e4 = &game.nodes[game.children[0]];
+        assert_eq!(e4.comments, ["best by test"]);
+        assert_eq!(e4.commands, [("clk".to_string(), "0:05:00".to_string())]);
+        assert_eq!(ucis(e4.children.iter().map(|i| &game.nodes[*i])), ["e7e5", "c7c5"]);
+        let c5 = &game.nodes[e4.children[1]];
+        assert_eq!(ucis(c5.children.iter().map(|i| &game.nodes[*i])), ["g1f3", "c2c3"]);
+
+        let mainline: Vec<_> = game.mainline().collect();
+        assert_eq!(mainline[1].nags, [1]);
+        assert_eq!(mainline[2].nags, [5]);
+        assert_eq!(mainline[3].comments, ["rest of the line"]);
+        // the boards handed out are the positions the moves are played in
+        for (mut board, node) in game.iter() {
+            assert!(board.get_moves().contains(&node.mv));
+        }
+    }
+
+    #[test]
+    fn read_errors() {
+        let input = "[Event \"1\"]\n\n1. e4 e5 *\n\n[Event \"2\"]\n\n1. e4 e4 *\n\n[Event \"3\"]\n\n1. d4 *\n";
+        let games: Vec<_> = PgnReader::new(input.as_bytes()).collect();
+        assert_eq!(games.len(), 3);
+        assert!(games[0].is_ok());
+        assert_eq!(
+            games[1],
+            Err(ChessError::Parse(
+                "line 7, column 7: InvalidMove(\"e4 is not a legal move\")".to_string()
+            ))
+        );
+        let game = games[2].as_ref().expect("pgn is valid");
+        assert_eq!((game.tag("Event"), game.nodes.len()), (Some("3"), 1));
+
+        for (input, expected) in [
+            ("1. e4 (1. d4", "line 1, column 13: Unclosed variation"),
+            ("1. e4 {oops", "line 1, column 7: Unterminated comment"),
+            ("[Event \"x]\n", "line 1, column 8: Unterminated string"),
+            ("1. e4 e5)", "line 1, column 9: Unmatched ')'"),
+            ("1. (e4)", "line 1, column 4: Variation before the first move"),
+        ] {
+            assert_eq!(Game::from_pgn(input), Err(ChessError::Parse(expected.to_string())), "{input}");
+        }
+    }
+
+    #[test]
+    fn fen_tag() {
+        let game = Game::from_pgn("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 w - - 0 1\"]\n\n1. e4 Kd7")
+            .expect("pgn is valid");
+        assert_eq!(game.start.fen(false), "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+        assert_eq!(ucis(game.mainline()), ["e2e4", "e8d7"]);
+        assert_eq!(game.result, "*");
+    }
+
+    #[test]
+    fn write_game() {
+        let mut game = Game::from_pgn(GAME).expect("pgn is valid");
+        let pgn = game.to_pgn();
+        assert_eq!(
+            pgn,
+            r#"[Event "Casual \"blitz\""]
+[Site "?"]
+[Date "2024.01.01"]
+[Round "1"]
+[White "A"]
+[Black "B"]
+[Result "1-0"]
+[WhiteElo "2000"]
+
+{Opening comment} 1. e4 {[%clk 0:05:00] best by test} 1... e5 $1 (1... c5 2.
+Nf3 (2. c3) 2... d6) 2. Nf3 $5 Nc6 {rest of the line} 3. Bb5 a6 1-0
+"#
+        );
+        assert_eq!(Game::from_pgn(&pgn), Ok(game.clone()));
+
+        // a `}` would end a comment early
+        let variation = game.nodes[0].children[1];
+        game.comments = vec!["a}b".to_string()];
+        game.nodes[variation].comments_before = vec!["c}d".to_string()];
+        game.nodes[variation].comments = vec!["e}f".to_string()];
+        let read = Game::from_pgn(&game.to_pgn()).expect("pgn is valid");
+        assert_eq!(read.comments, ["ab"]);
+        assert_eq!(read.nodes[variation].comments_before, ["cd"]);
+        assert_eq!(read.nodes[variation].comments, ["ef"]);
+
+        // parentheses in comments keep their spaces
+        game.comments = vec!["see ( and ) here".to_string()];
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("{see ( and ) here}"), "{pgn}");
+        assert_eq!(Game::from_pgn(&pgn).expect("pgn is valid").comments, ["see ( and ) here"]);
+
+        // black moves first in a set up position
+        let fen = "4k3/8/8/8/8/8/8/R3K3 b - - 0 30";
+        let mut board = Board::from_fen(fen).expect("fen is valid");
+        let mut game = Game::new(board);
+        for mv in ["e8d8", "a1a2", "d8e8", "a2a1"].repeat(10) {
+            let mv = Move::from_str(mv, &mut board).expect("move is legal");
+            board.make_move(&mv);
+            game.push(mv);
+        }
+        let pgn = game.to_pgn();
+        assert!(pgn.contains(&format!("[Result \"*\"]\n[SetUp \"1\"]\n[FEN \"{fen}\"]\n\n30... Kd8 31. Ra2 Ke8")));
+        assert!(pgn.lines().all(|line| line.len() <= 79));
+        let read = Game::from_pgn(&pgn).expect("pgn is valid");
+        assert_eq!((read.start, read.nodes), (game.start, game.nodes));
+    }
+}
@@ -0,0 +1,231 @@
+use util::{
+    error::ChessError,
+    piece::Piece,
+    square::{File, Rank, Square},
+};
+
+use crate::{
+    board::Board,
+    mv::{Move, MoveFlag, Promotion},
+};
+
+fn piece_letter(piece: &Piece) -> Option<char> {
+    match piece {
+        Piece::Pawn => None,
+        piece => Some(piece.to_char().to_ascii_uppercase()),
+    }
+}
+
+/// Figurine glyphs of both colors as the piece letter, pawns are written without one
+fn replace_figurines(input: &str) -> String {
+    input
+        .chars()
+        .filter_map(|c| match c {
+            '♔' | '♚' => Some('K'),
+            '♕' | '♛' => Some('Q'),
+            '♖' | '♜' => Some('R'),
+            '♗' | '♝' => Some('B'),
+            '♘' | '♞' => Some('N'),
+            '♙' | '♟' => None,
+            c => Some(c),
+        })
+        .collect()
+}
+
+impl Board {
+    /// Writes `mv` in standard algebraic notation, eg. `Nbd7`, `exd6`, `e8=Q+` or `O-O-O#`
+    pub fn to_san(&mut self, mv: &Move) -> String {
+        let mut san = match mv.flag {
+            MoveFlag::KingSideCastles => "O-O".to_string(),
+            MoveFlag::QueenSideCastles => "O-O-O".to_string(),
+            _ => {
+                let mut san = String::new();
+                let capture = matches!(mv.flag, MoveFlag::Capture(_) | MoveFlag::EnPassant);
+                match piece_letter(&mv.piece) {
+                    Some(letter) => {
+                        san.push(letter);
+                        san += &self.disambiguation(mv);
+                    }
+                    None if capture => san.push(mv.from.file().to_char()),
+                    None => {}
+                }
+                if capture {
+                    san.push('x');
+                }
+                san += &mv.to.to_string();
+                if let Some(promotion) = mv.promotion {
+                    san.push('=');
+                    san.extend(piece_letter(&promotion.piece()));
+                }
+                san
+            }
+        };
+
+        let undo = self.make_move(mv);
+        if self.in_check() {
+            san.push(if self.get_moves().is_empty() { '#' } else { '+' });
+        }
+        self.unmake_move(mv, undo);
+        san
+    }
+
+    /// The from file, rank or square needed to tell `mv` apart from the same piece type moving to the same square
+    fn disambiguation(&mut self, mv: &Move) -> String {
+        let others: Vec<_> = self
+            .get_moves()
+            .iter()
+            .filter(|other| {
+                other.piece == mv.piece && other.to == mv.to && other.from != mv.from && !other.is_castling()
+            })
+            .map(|other| other.from)
+            .collect();
+        if others.is_empty() {
+            String::new()
+        } else if others.iter().all(|sq| sq.file() != mv.from.file()) {
+            mv.from.file().to_char().to_string()
+        } else if others.iter().all(|sq| sq.rank() != mv.from.rank()) {
+            mv.from.rank().to_char().to_string()
+        } else {
+            mv.from.to_string()
+        }
+    }
+
+    /// Reads a move in standard algebraic notation. Also takes `0-0` for castling, check and mate signs
+    /// that are missing or wrong, promotions without `=`, long algebraic like `Ng1-f3` and figurine glyphs.
+    pub fn parse_san(&mut self, input: &str) -> Result<Move, ChessError> {
+        let san = replace_figurines(input.trim());
+        let san = san.trim_end_matches(['+', '#', '!', '?']);
+        let invalid = || ChessError::InvalidMove(format!("{input} is not a legal move"));
+
+        let castling = match san {
+            "O-O" | "0-0" | "o-o" => Some(MoveFlag::KingSideCastles),
+            "O-O-O" | "0-0-0" | "o-o-o" => Some(MoveFlag::QueenSideCastles),
+            _ => None,
+        };
+        if let Some(flag) = castling {
+            return self.get_moves().iter().find(|mv| mv.flag == flag).copied().ok_or_else(invalid);
+        }
+
+        let mut chars: Vec<char> = san.chars().filter(|c| !matches!(c, 'x' | ':' | '-')).collect();
+        let piece = match chars.first() {
+            Some(c @ ('K' | 'Q' | 'R' | 'B' | 'N')) => {
+                let piece = Piece::from_char(c.to_ascii_lowercase()).expect("is a piece letter");
+                chars.remove(0);
+                piece
+            }
+            _ => Piece::Pawn,
+        };
+
+        // `e8=Q`, `e8Q` or `e8q`
+        let promotion = match chars.as_slice() {
+            [.., prev, promotion] if (prev.is_ascii_digit() || *prev == '=') && promotion.is_ascii_alphabetic() => {
+                let promotion = Promotion::from_str(&promotion.to_ascii_lowercase().to_string())?;
+                chars.pop();
+                Some(promotion)
+            }
+            _ => None,
+        };
+        if chars.last() == Some(&'=') {
+            chars.pop();
+        }
+
+        let parse_err = || ChessError::Parse(format!("'{input}' is not a move in algebraic notation"));
+        if chars.len() < 2 {
+            return Err(parse_err());
+        }
+        let to: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let to = Square::from_str(&to)?;
+        let (mut from_file, mut from_rank) = (None, None);
+        for c in chars {
+            match (File::from_char(c), Rank::from_char(c)) {
+                (Some(file), _) => from_file = Some(file),
+                (_, Some(rank)) => from_rank = Some(rank),
+                _ => return Err(parse_err()),
+            }
+        }
+
+        let mut candidates = self.get_moves().into_iter().filter(|mv| {
+            mv.piece == piece
+                && mv.to == to
+                && mv.promotion == promotion
+                && !mv.is_castling()
+                && from_file.is_none_or(|file| mv.from.file() == file)
+                && from_rank.is_none_or(|rank| mv.from.rank() == rank)
+        });
+        match (candidates.next(), candidates.next()) {
+            (Some(mv), None) => Ok(mv),
+            (Some(_), Some(_)) => Err(ChessError::InvalidMove(format!("{input} is ambiguous"))),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::Board, mv::Move};
+
+    #[test]
+    fn to_san() {
+        for (fen, mv, san) in [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "g1f3", "Nf3"),
+            ("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", "f3e5", "Nxe5"),
+            ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", "e5f6", "exf6"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1g1", "O-O"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8c8", "O-O-O"),
+            ("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q", "b8=Q+"),
+            ("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8n", "b8=N"),
+            ("6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1", "a1a8", "Ra8#"),
+            // disambiguation by file, rank and both
+            ("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1", "b1d2", "Nbd2"),
+            ("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3", "R1a3"),
+            ("1k6/8/8/8/7Q/8/8/K6Q w - - 0 1", "h4e1", "Q4e1"),
+            ("1k6/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", "h4e1", "Qh4e1"),
+            // a pinned knight does not need to be told apart
+            ("4k3/8/8/8/8/8/8/1N2KN1r w - - 0 1", "b1d2", "Nd2"),
+        ] {
+            let mut board = Board::from_fen(fen).expect("fen is valid");
+            let mv = Move::from_str(mv, &mut board).expect("move is legal");
+            assert_eq!(board.to_san(&mv), san, "{fen}");
+            assert_eq!(board.parse_san(san), Ok(mv), "{fen}");
+        }
+    }
+
+    #[test]
+    fn parse_san_variants() {
+        let mut board = Board::from_fen("r3k2r/1P6/8/8/8/8/8/R3K1NR w KQkq - 0 1").expect("fen is valid");
+        for (input, expected) in [
+            ("0-0-0", "e1c1"),
+            ("O-O-O+", "e1c1"),
+            ("b8Q", "b7b8q"),
+            ("bxa8=N", "b7a8n"),
+            ("bxa8r", "b7a8r"),
+            ("b7xa8=B!", "b7a8b"),
+            ("♘f3", "g1f3"),
+            ("Ng1-f3", "g1f3"),
+            ("Nf3+", "g1f3"),
+        ] {
+            let expected = Move::from_str(expected, &mut board).expect("move is legal");
+            assert_eq!(board.parse_san(input), Ok(expected), "{input}");
+        }
+        for input in ["O-O", "b8", "Nf4", "Ra2e2", "Zf3", "x"] {
+            assert!(board.parse_san(input).is_err(), "{input}");
+        }
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1").expect("fen is valid");
+        assert!(board.parse_san("Nd2").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9",
+        ] {
+            let mut board = Board::from_fen(fen).expect("fen is valid");
+            for mv in &board.get_moves() {
+                let san = board.to_san(mv);
+                assert_eq!(board.parse_san(&san), Ok(*mv), "{fen} {san}");
+            }
+        }
+    }
+}
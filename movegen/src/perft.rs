@@ -0,0 +1,453 @@
+use std::{
+    collections::VecDeque,
+    ops::AddAssign,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use util::{
+    error::ChessError,
+    square::{File, Rank, Square},
+};
+
+use crate::{
+    board::Board,
+    mv::{Move, MoveFlag},
+    parse::epd::Epd,
+};
+
+/// Zobrist keyed cache of perft subtree counts.
+/// Entries are stored as `key ^ data` next to `data`, so a torn write from another thread is detected as a miss
+/// and the table can be shared between threads without locking.
+pub struct PerftTable {
+    entries: Vec<(AtomicU64, AtomicU64)>,
+}
+
+impl PerftTable {
+    /// A table using at most `megabytes` of memory, rounded down to a power of two number of entries
+    pub fn new(megabytes: usize) -> Self {
+        let max_entries = (megabytes << 20) / std::mem::size_of::<(AtomicU64, AtomicU64)>();
+        let len = if max_entries == 0 { 1 } else { 1 << max_entries.ilog2() };
+        Self {
+            entries: (0..len).map(|_| (AtomicU64::new(0), AtomicU64::new(0))).collect(),
+        }
+    }
+
+    fn entry(&self, key: u64) -> &(AtomicU64, AtomicU64) {
+        &self.entries[key as usize & (self.entries.len() - 1)]
+    }
+
+    pub fn probe(&self, key: u64, depth: u8) -> Option<u64> {
+        let (checked_key, data) = self.entry(key);
+        let data = data.load(Ordering::Relaxed);
+        if checked_key.load(Ordering::Relaxed) ^ data == key && data as u8 == depth {
+            Some(data >> 8)
+        } else {
+            None
+        }
+    }
+
+    pub fn store(&self, key: u64, depth: u8, count: u64) {
+        let (checked_key, data) = self.entry(key);
+        let new_data = count << 8 | depth as u64;
+        checked_key.store(key ^ new_data, Ordering::Relaxed);
+        data.store(new_data, Ordering::Relaxed);
+    }
+}
+
+/// Breakdown of the moves leading to the leaf nodes of a perft tree,
+/// counted the same way as the tables on https://www.chessprogramming.org/Perft_Results
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PerftStats {
+    pub nodes: u64,
+    /// Including en passant captures
+    pub captures: u64,
+    pub en_passant: u64,
+    pub castles: u64,
+    pub promotions: u64,
+    /// Including discovered and double checks
+    pub checks: u64,
+    /// Single checks given by a piece other than the one that moved
+    pub discovered_checks: u64,
+    pub double_checks: u64,
+    pub checkmates: u64,
+}
+
+impl AddAssign for PerftStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.nodes += rhs.nodes;
+        self.captures += rhs.captures;
+        self.en_passant += rhs.en_passant;
+        self.castles += rhs.castles;
+        self.promotions += rhs.promotions;
+        self.checks += rhs.checks;
+        self.discovered_checks += rhs.discovered_checks;
+        self.double_checks += rhs.double_checks;
+        self.checkmates += rhs.checkmates;
+    }
+}
+
+impl PerftStats {
+    /// Stats of the leaf reached by `mv`, which `board` has just made
+    fn leaf(board: &mut Board, mv: &Move) -> Self {
+        let checkers = board.check_masks.iter().flatten().map(|(sq, _, _)| *sq);
+        let in_check = board.in_check();
+        let double = checkers.clone().count() == 2;
+        // After castling the rook on its destination checks directly, just like the moved piece on `mv.to`
+        let rook_dest = match mv.flag {
+            MoveFlag::KingSideCastles => Some(File::F),
+            MoveFlag::QueenSideCastles => Some(File::D),
+            _ => None,
+        }
+        .map(|file| Square::from_rank_file(Rank::First.pov(&!board.state.active_color), file));
+        let discovered = !double && checkers.into_iter().any(|sq| sq != mv.to && Some(sq) != rook_dest);
+        let mate = in_check && board.get_moves().is_empty();
+        Self {
+            nodes: 1,
+            captures: matches!(mv.flag, MoveFlag::Capture(_) | MoveFlag::EnPassant) as u64,
+            en_passant: (mv.flag == MoveFlag::EnPassant) as u64,
+            castles: matches!(mv.flag, MoveFlag::KingSideCastles | MoveFlag::QueenSideCastles) as u64,
+            promotions: mv.promotion.is_some() as u64,
+            checks: in_check as u64,
+            discovered_checks: discovered as u64,
+            double_checks: double as u64,
+            checkmates: mate as u64,
+        }
+    }
+}
+
+/// One position of a perft suite, read from a line like `<fen> ;D1 20 ;D2 400`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PerftSuiteEntry {
+    pub fen: String,
+    /// Expected node counts by depth
+    pub depths: Vec<(u8, u64)>,
+}
+
+impl PerftSuiteEntry {
+    /// Reads the `D<depth>` operations of an EPD line, other than the move counters they are the only ones allowed
+    pub fn from_epd(line: &str) -> Result<Self, ChessError> {
+        line.parse::<Epd>()?.try_into()
+    }
+
+    /// Reads every non-empty line of `input` that does not start with `#`
+    pub fn read_suite(input: &str) -> Result<Vec<Self>, ChessError> {
+        Epd::read_suite(input)?.into_iter().map(Self::try_from).collect()
+    }
+}
+
+impl TryFrom<Epd> for PerftSuiteEntry {
+    type Error = ChessError;
+
+    fn try_from(epd: Epd) -> Result<Self, Self::Error> {
+        let mut depths = Vec::new();
+        for (opcode, operands) in epd.opcodes.iter().filter(|(opcode, _)| opcode != "hmvc" && opcode != "fmvn") {
+            let err = || {
+                ChessError::Parse(format!("'{opcode} {}' is not a perft depth like 'D1 20'", operands.join(" ")))
+            };
+            let depth = opcode.strip_prefix('D').and_then(|d| d.parse().ok()).ok_or_else(err)?;
+            let [count] = operands.as_slice() else {
+                return Err(err());
+            };
+            depths.push((depth, count.parse().map_err(|_| err())?));
+        }
+        if depths.is_empty() {
+            return Err(ChessError::Parse(format!("'{}' needs at least one depth", epd.board.epd())));
+        }
+        Ok(Self { fen: epd.board.fen(false), depths })
+    }
+}
+
+/// Subtrees shallower than this are always counted by a single worker
+const MIN_SPLIT_DEPTH: u8 = 3;
+/// Workers stop splitting subtrees while at least this many tasks per worker are waiting
+const QUEUED_TASKS_PER_WORKER: usize = 4;
+
+/// A subtree to count
+struct Task {
+    board: Board,
+    depth: u8,
+}
+
+/// Thread pool for `perft_multithread`. Every worker pushes and pops tasks at the back of its own deque
+/// and steals from the front of the others' deques when it runs out, which takes the biggest waiting subtrees.
+struct Pool<'a> {
+    deques: Vec<Mutex<VecDeque<Task>>>,
+    /// Tasks waiting in any deque
+    queued: AtomicUsize,
+    /// Tasks waiting or being worked on, the pool is done when this reaches zero
+    pending: AtomicUsize,
+    total: AtomicU64,
+    table: Option<&'a PerftTable>,
+}
+
+impl<'a> Pool<'a> {
+    fn run(board: Board, depth: u8, thread_count: usize, table: Option<&'a PerftTable>) -> u64 {
+        let thread_count = thread_count.max(1);
+        let pool = Self {
+            deques: (0..thread_count).map(|_| Mutex::new(VecDeque::new())).collect(),
+            queued: AtomicUsize::new(0),
+            pending: AtomicUsize::new(0),
+            total: AtomicU64::new(0),
+            table,
+        };
+        pool.push(0, vec![Task { board, depth }]);
+
+        thread::scope(|scope| {
+            for worker in 0..thread_count {
+                let pool = &pool;
+                scope.spawn(move || pool.work(worker));
+            }
+        });
+        pool.total.into_inner()
+    }
+
+    fn push(&self, worker: usize, tasks: Vec<Task>) {
+        self.pending.fetch_add(tasks.len(), Ordering::AcqRel);
+        self.queued.fetch_add(tasks.len(), Ordering::AcqRel);
+        self.deques[worker].lock().expect("worker panicked").extend(tasks);
+    }
+
+    fn pop(&self, worker: usize) -> Option<Task> {
+        let own = self.deques[worker].lock().expect("worker panicked").pop_back();
+        let task = own.or_else(|| {
+            (1..self.deques.len())
+                .map(|offset| (worker + offset) % self.deques.len())
+                .find_map(|victim| self.deques[victim].lock().expect("worker panicked").pop_front())
+        })?;
+        self.queued.fetch_sub(1, Ordering::AcqRel);
+        Some(task)
+    }
+
+    fn work(&self, worker: usize) {
+        loop {
+            match self.pop(worker) {
+                Some(task) => {
+                    self.count(worker, task);
+                    self.pending.fetch_sub(1, Ordering::AcqRel);
+                }
+                None if self.pending.load(Ordering::Acquire) == 0 => return,
+                None => thread::yield_now(),
+            }
+        }
+    }
+
+    /// Counts the subtree of `task`, or splits it into one task per move when other workers may run out of work
+    fn count(&self, worker: usize, mut task: Task) {
+        let should_split = task.depth >= MIN_SPLIT_DEPTH
+            && self.queued.load(Ordering::Acquire) < self.deques.len() * QUEUED_TASKS_PER_WORKER;
+        let cached = self.table.and_then(|table| table.probe(task.board.zobrist, task.depth));
+        let count = match cached {
+            Some(count) => count,
+            None if should_split => {
+                let mut children = Vec::new();
+                task.board.generate_moves(|board: &mut Board, mv: &Move| {
+                    let mut child = *board;
+                    child.make_move(mv);
+                    children.push(Task { board: child, depth: task.depth - 1 });
+                });
+                self.push(worker, children);
+                return;
+            }
+            None => task.board.perft_inner(task.depth, self.table),
+        };
+        self.total.fetch_add(count, Ordering::AcqRel);
+    }
+}
+
+impl Board {
+    /// Like `perft`, with the tree split between `thread_count` threads
+    pub fn perft_multithread(&mut self, depth: u8, thread_count: usize) -> u64 {
+        Pool::run(*self, depth, thread_count, None)
+    }
+
+    /// Like `perft_multithread`, with all threads sharing `table`
+    pub fn perft_multithread_with_table(&mut self, depth: u8, thread_count: usize, table: &PerftTable) -> u64 {
+        Pool::run(*self, depth, thread_count, Some(table))
+    }
+
+    /// Counts the leaf nodes of the legal move tree `depth` plies deep.
+    /// The last ply is counted without making the moves.
+    pub fn perft(&mut self, depth: u8) -> u64 {
+        self.perft_inner(depth, None)
+    }
+
+    /// Like `perft`, caching subtree counts in `table`
+    pub fn perft_with_table(&mut self, depth: u8, table: &PerftTable) -> u64 {
+        self.perft_inner(depth, Some(table))
+    }
+
+    /// The perft count below each legal move, in generation order
+    pub fn divide(&mut self, depth: u8, table: Option<&PerftTable>) -> Vec<(Move, u64)> {
+        let mut counts = Vec::new();
+        if depth == 0 {
+            return counts;
+        }
+        self.generate_moves(|board: &mut Self, mv: &Move| {
+            let undo = board.make_move(mv);
+            counts.push((*mv, board.perft_inner(depth - 1, table)));
+            board.unmake_move(mv, undo);
+        });
+        counts
+    }
+
+    /// Like `perft`, but also breaks the leaf nodes down by the move leading to them.
+    /// Every leaf move is made, so this is a lot slower.
+    pub fn perft_stats(&mut self, depth: u8) -> PerftStats {
+        let mut stats = PerftStats::default();
+        if depth == 0 {
+            stats.nodes = 1;
+            return stats;
+        }
+        self.generate_moves(|board: &mut Self, mv: &Move| {
+            let undo = board.make_move(mv);
+            stats += if depth == 1 {
+                PerftStats::leaf(board, mv)
+            } else {
+                board.perft_stats(depth - 1)
+            };
+            board.unmake_move(mv, undo);
+        });
+        stats
+    }
+
+    fn perft_inner(&mut self, depth: u8, table: Option<&PerftTable>) -> u64 {
+        let mut total = 0;
+        match depth {
+            0 => return 1,
+            1 => {
+                self.generate_moves(|_: &mut Self, _: &Move| total += 1);
+                return total;
+            }
+            _ => {}
+        }
+
+        if let Some(count) = table.and_then(|table| table.probe(self.zobrist, depth)) {
+            return count;
+        }
+        self.generate_moves(|board: &mut Self, mv: &Move| {
+            let undo = board.make_move(mv);
+            total += board.perft_inner(depth - 1, table);
+            board.unmake_move(mv, undo);
+        });
+        if let Some(table) = table {
+            table.store(self.zobrist, depth, total);
+        }
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn perft() {
+        let mut board = Board::from_fen(KIWIPETE).expect("fen is valid");
+        let expected = [1, 48, 2_039, 97_862];
+        for (depth, count) in expected.iter().enumerate() {
+            assert_eq!(board.perft(depth as u8), *count);
+        }
+
+        let divide = board.divide(3, None);
+        assert_eq!(divide.len(), 48);
+        assert_eq!(divide.iter().map(|(_, count)| count).sum::<u64>(), 97_862);
+    }
+
+    fn stats(nodes: u64, captures: u64, en_passant: u64, castles: u64, promotions: u64, checks: [u64; 3], checkmates: u64) -> PerftStats {
+        let [checks, discovered_checks, double_checks] = checks;
+        PerftStats { nodes, captures, en_passant, castles, promotions, checks, discovered_checks, double_checks, checkmates }
+    }
+
+    #[test]
+    fn perft_stats() {
+        let positions = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", vec![
+                stats(20, 0, 0, 0, 0, [0, 0, 0], 0),
+                stats(400, 0, 0, 0, 0, [0, 0, 0], 0),
+                stats(8_902, 34, 0, 0, 0, [12, 0, 0], 0),
+                stats(197_281, 1_576, 0, 0, 0, [469, 0, 0], 8),
+            ]),
+            (KIWIPETE, vec![
+                stats(48, 8, 0, 2, 0, [0, 0, 0], 0),
+                stats(2_039, 351, 1, 91, 0, [3, 0, 0], 0),
+                stats(97_862, 17_102, 45, 3_162, 0, [993, 0, 0], 1),
+            ]),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", vec![
+                stats(14, 1, 0, 0, 0, [2, 0, 0], 0),
+                stats(191, 14, 0, 0, 0, [10, 0, 0], 0),
+                stats(2_812, 209, 2, 0, 0, [267, 3, 0], 0),
+                stats(43_238, 3_348, 123, 0, 0, [1_680, 106, 0], 17),
+            ]),
+            ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", vec![
+                stats(6, 0, 0, 0, 0, [0, 0, 0], 0),
+                stats(264, 87, 0, 6, 48, [10, 0, 0], 0),
+                stats(9_467, 1_021, 4, 0, 120, [38, 2, 0], 22),
+            ]),
+            // O-O checks with the rook, which is a direct check and not a discovered one
+            ("5k2/8/8/8/8/8/8/4K2R w K - 0 1", vec![
+                stats(15, 0, 0, 1, 0, [3, 0, 0], 0),
+            ]),
+        ];
+        for (fen, expected) in positions {
+            let mut board = Board::from_fen(fen).expect("fen is valid");
+            for (depth, expected) in expected.iter().enumerate() {
+                assert_eq!(board.perft_stats(depth as u8 + 1), *expected, "{fen} at depth {}", depth + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn multithread() {
+        let mut board = Board::from_fen(KIWIPETE).expect("fen is valid");
+        let table = PerftTable::new(1);
+        for thread_count in [1, 3] {
+            for (depth, count) in [1, 48, 2_039, 97_862].iter().enumerate() {
+                assert_eq!(board.perft_multithread(depth as u8, thread_count), *count);
+                assert_eq!(board.perft_multithread_with_table(depth as u8, thread_count, &table), *count);
+            }
+        }
+    }
+
+    #[test]
+    fn perft_suite() {
+        let suite = "# comment\n\
+            rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 ;D1 20 ;D2 400\n\
+            \n\
+            4k3/8/8/8/8/8/8/4K2R w K - ;D1 15";
+        assert_eq!(
+            PerftSuiteEntry::read_suite(suite).expect("suite is valid"),
+            [
+                PerftSuiteEntry {
+                    fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
+                    depths: vec![(1, 20), (2, 400)],
+                },
+                PerftSuiteEntry { fen: "4k3/8/8/8/8/8/8/4K2R w K - 0 1".to_string(), depths: vec![(1, 15)] },
+            ]
+        );
+        assert!(PerftSuiteEntry::read_suite("4k3/8/8/8/8/8/8/4K2R w K - ;D1").is_err());
+        assert!(PerftSuiteEntry::read_suite("4k3/8/8/8/8/8/8/4K2R w K - ;X1 15").is_err());
+        assert!(PerftSuiteEntry::read_suite("4k3/8/8/8/8/8/8/4K2R w K -").is_err());
+        assert!(PerftSuiteEntry::read_suite("4k3/8/8/8/8/8/8/4K2R w K - ;D1 15 16").is_err());
+        let entry = PerftSuiteEntry::from_epd("4k3/8/8/8/8/8/8/4K2R w K - 0 7 ;D1 15").expect("line is valid");
+        assert_eq!(entry.fen, "4k3/8/8/8/8/8/8/4K2R w K - 0 7");
+    }
+
+    #[test]
+    fn table() {
+        // A single entry makes every store overwrite a different position
+        for table in [PerftTable::new(0), PerftTable::new(1)] {
+            let mut board = Board::from_fen(KIWIPETE).expect("fen is valid");
+            assert_eq!(board.perft_with_table(3, &table), 97_862);
+            assert_eq!(board.perft_with_table(3, &table), 97_862);
+            assert_eq!(board.perft_with_table(2, &table), 2_039);
+            let divide = board.divide(3, Some(&table));
+            assert_eq!(divide.iter().map(|(_, count)| count).sum::<u64>(), 97_862);
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::ops::{Index, IndexMut};
 use util::{bitboard::Bitboard, piece::Piece};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct PieceBitboards {
     pub all: Bitboard,
     pub pawn: Bitboard,
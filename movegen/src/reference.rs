@@ -0,0 +1,375 @@
+use util::{
+    color::Color,
+    piece::Piece,
+    square::{File, Rank, Square},
+};
+
+use crate::{
+    bisect::DivideReference,
+    board::Board,
+    builder::BoardBuilder,
+    mv::{Move, MoveFlag, Promotion},
+    state::Castling,
+    zobrist::splitmix64,
+};
+
+const KNIGHT_DELTAS: [(i8, i8); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+const KING_DELTAS: [(i8, i8); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+const DIAGONALS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+const STRAIGHTS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+/// Looks through the piece bitboards instead of trusting the mailbox
+fn piece_on(board: &Board, sq: Square) -> Option<(Color, Piece)> {
+    Color::ALL
+        .iter()
+        .flat_map(|color| Piece::ALL.iter().map(move |piece| (*color, *piece)))
+        .find(|(color, piece)| board.pieces[color][piece].has_sq(sq))
+}
+
+fn pawn_direction(color: &Color) -> i8 {
+    match color {
+        Color::White => 1,
+        Color::Black => -1,
+    }
+}
+
+/// Whether a piece of `color` attacks `sq`, walking the board square by square.
+/// `ignore` is treated as empty.
+fn is_attacked(board: &Board, sq: Square, color: Color, ignore: Option<Square>) -> bool {
+    let occupant = |sq: Square| piece_on(board, sq).filter(|_| Some(sq) != ignore);
+    let attacker = |sq: Option<Square>, pieces: &[Piece]| match sq.and_then(occupant) {
+        Some((c, piece)) => c == color && pieces.contains(&piece),
+        None => false,
+    };
+
+    let pawn_rank = -pawn_direction(&color);
+    if attacker(sq.apply_delta((pawn_rank, 1)), &[Piece::Pawn])
+        || attacker(sq.apply_delta((pawn_rank, -1)), &[Piece::Pawn])
+        || KNIGHT_DELTAS.iter().any(|delta| attacker(sq.apply_delta(*delta), &[Piece::Knight]))
+        || KING_DELTAS.iter().any(|delta| attacker(sq.apply_delta(*delta), &[Piece::King]))
+    {
+        return true;
+    }
+
+    let slider = |deltas: &[(i8, i8)], pieces: &[Piece]| {
+        deltas.iter().any(|delta| {
+            let mut curr = sq;
+            while let Some(next) = curr.apply_delta(*delta) {
+                if occupant(next).is_some() {
+                    return attacker(Some(next), pieces);
+                }
+                curr = next;
+            }
+            false
+        })
+    };
+    slider(&DIAGONALS, &[Piece::Bishop, Piece::Queen]) || slider(&STRAIGHTS, &[Piece::Rook, Piece::Queen])
+}
+
+impl Board {
+    /// A deliberately simple move generator to check `generate_moves` against.
+    /// Generates pseudo-legal moves square by square, then drops those leaving the own king attacked.
+    pub fn reference_moves(&self) -> Vec<Move> {
+        let color = self.state.active_color;
+        self.reference_pseudo_legal_moves()
+            .into_iter()
+            .filter(|mv| {
+                let mut board = *self;
+                board.make_move(mv);
+                let king_sq = board.pieces[&color].king.next_sq().expect("there is a king");
+                !is_attacked(&board, king_sq, !color, None)
+            })
+            .collect()
+    }
+
+    /// Perft using `reference_moves`, without any shortcuts
+    pub fn reference_perft(&self, depth: u8) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        self.reference_moves()
+            .iter()
+            .map(|mv| {
+                let mut board = *self;
+                board.make_move(mv);
+                board.reference_perft(depth - 1)
+            })
+            .sum()
+    }
+
+    fn reference_pseudo_legal_moves(&self) -> Vec<Move> {
+        let color = self.state.active_color;
+        let mut moves = Vec::new();
+        let mut add = |piece: Piece, from: Square, to: Square, flag: MoveFlag| {
+            moves.push(Move { piece, from, to, flag, promotion: None })
+        };
+
+        for from in (0..64).map(Square::from_u8) {
+            let piece = match piece_on(self, from) {
+                Some((c, piece)) if c == color => piece,
+                _ => continue,
+            };
+            // None if the square is off the board or taken by an own piece
+            let target = |to: Option<Square>| {
+                let to = to?;
+                match piece_on(self, to) {
+                    Some((c, _)) if c == color => None,
+                    Some((_, captured)) => Some((to, MoveFlag::Capture(captured))),
+                    None => Some((to, MoveFlag::None)),
+                }
+            };
+
+            match piece {
+                Piece::Pawn => {
+                    let dir = pawn_direction(&color);
+                    if let Some(to) = from.apply_delta((dir, 0)).filter(|to| piece_on(self, *to).is_none()) {
+                        add(piece, from, to, MoveFlag::None);
+                        let double = to.apply_delta((dir, 0)).filter(|to| piece_on(self, *to).is_none());
+                        if let (Rank::Second, Some(double)) = (from.rank().pov(&color), double) {
+                            add(piece, from, double, MoveFlag::PawnFirstMove);
+                        }
+                    }
+                    for file_delta in [-1, 1] {
+                        let Some(to) = from.apply_delta((dir, file_delta)) else {
+                            continue;
+                        };
+                        match piece_on(self, to) {
+                            Some((c, captured)) if c != color => add(piece, from, to, MoveFlag::Capture(captured)),
+                            None if self.state.ep_file.map(|file| Square::ep_move_sq(&!color, file)) == Some(to) => {
+                                add(piece, from, to, MoveFlag::EnPassant)
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                Piece::Knight | Piece::King => {
+                    let deltas = if piece == Piece::Knight { KNIGHT_DELTAS } else { KING_DELTAS };
+                    for delta in deltas {
+                        if let Some((to, flag)) = target(from.apply_delta(delta)) {
+                            add(piece, from, to, flag);
+                        }
+                    }
+                }
+                _ => {
+                    let deltas = match piece {
+                        Piece::Bishop => DIAGONALS.to_vec(),
+                        Piece::Rook => STRAIGHTS.to_vec(),
+                        _ => [DIAGONALS, STRAIGHTS].concat(),
+                    };
+                    for delta in deltas {
+                        let mut curr = from;
+                        while let Some((to, flag)) = target(curr.apply_delta(delta)) {
+                            add(piece, from, to, flag);
+                            if flag != MoveFlag::None {
+                                break;
+                            }
+                            curr = to;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Pawns reaching the last rank become one of four promotions instead
+        let mut moves: Vec<_> = moves
+            .into_iter()
+            .flat_map(|mv| {
+                let promotions = if mv.piece == Piece::Pawn && mv.to.rank() == Rank::Eighth.pov(&color) {
+                    Promotion::ALL.map(Some).to_vec()
+                } else {
+                    vec![None]
+                };
+                promotions.into_iter().map(move |promotion| Move { promotion, ..mv })
+            })
+            .collect();
+        moves.extend(self.reference_castling_moves());
+        moves
+    }
+
+    fn reference_castling_moves(&self) -> Vec<Move> {
+        let color = self.state.active_color;
+        let castling = self.state.castling[&color];
+        let rank = Rank::First.pov(&color);
+        let king_sq = Square::from_rank_file(rank, castling.king_file);
+        if piece_on(self, king_sq) != Some((color, Piece::King)) || is_attacked(self, king_sq, !color, None) {
+            return Vec::new();
+        }
+
+        let mut moves = Vec::new();
+        for (rook_file, king_dest_file, rook_dest_file, flag) in [
+            (castling.king_side, File::G, File::F, MoveFlag::KingSideCastles),
+            (castling.queen_side, File::C, File::D, MoveFlag::QueenSideCastles),
+        ] {
+            let Some(rook_file) = rook_file else {
+                continue;
+            };
+            let rook_sq = Square::from_rank_file(rank, rook_file);
+            if piece_on(self, rook_sq) != Some((color, Piece::Rook)) {
+                continue;
+            }
+            // Every square from where the king and rook stand to where they go must be empty, apart from the two
+            let files = |a: File, b: File| {
+                let (a, b) = (a as u8, b as u8);
+                (a.min(b)..=a.max(b)).map(|f| Square::from_rank_file(rank, File::from_u8(f)))
+            };
+            let blocked = files(castling.king_file, king_dest_file)
+                .chain(files(rook_file, rook_dest_file))
+                .any(|sq| sq != king_sq && sq != rook_sq && piece_on(self, sq).is_some());
+            // The king may not pass an attacked square, with the rook out of the way
+            let attacked = files(castling.king_file, king_dest_file)
+                .any(|sq| sq != king_sq && is_attacked(self, sq, !color, Some(rook_sq)));
+            if !blocked && !attacked {
+                let to = Square::from_rank_file(rank, king_dest_file);
+                moves.push(Move { piece: Piece::King, from: king_sq, to, flag, promotion: None });
+            }
+        }
+        moves
+    }
+}
+
+/// Uses `reference_perft` as the expected divide when bisecting
+pub struct ReferenceGenerator;
+
+impl DivideReference for ReferenceGenerator {
+    fn divide(&mut self, board: &Board, depth: u8) -> Option<Vec<(String, u64)>> {
+        let divide = board
+            .reference_moves()
+            .iter()
+            .map(|mv| {
+                let mut after = *board;
+                after.make_move(mv);
+                (board.move_to_string(mv), after.reference_perft(depth - 1))
+            })
+            .collect();
+        Some(divide)
+    }
+}
+
+/// Start positions for fuzzing, chosen for castling, en passant, promotions and pins
+pub const FUZZ_SEED_POSITIONS: [&str; 6] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9",
+];
+
+/// A position where the two generators disagree
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzMismatch {
+    pub fen: String,
+    /// Generated by `reference_moves` only
+    pub missing: Vec<String>,
+    /// Generated by `get_moves` only
+    pub extra: Vec<String>,
+}
+
+/// Plays `games` random games of up to `plies` moves and compares `get_moves` with `reference_moves` in every position.
+/// Games start from the seed positions, random chess960 start positions or randomly scattered pieces.
+/// Returns the number of positions compared.
+pub fn fuzz(seed: u64, games: usize, plies: usize) -> Result<usize, FuzzMismatch> {
+    let mut rand = seed;
+    let mut positions = 0;
+    for game in 0..games {
+        let mut board = match game % 3 {
+            0 => Board::from_fen(FUZZ_SEED_POSITIONS[game / 3 % FUZZ_SEED_POSITIONS.len()]).expect("fen is valid"),
+            1 => random_chess960(&mut rand),
+            _ => random_position(&mut rand),
+        };
+        for _ in 0..plies {
+            let moves = board.get_moves();
+            let reference = board.reference_moves();
+            positions += 1;
+            let missing: Vec<_> = reference.iter().filter(|mv| !moves.contains(mv)).collect();
+            let extra: Vec<_> = moves.iter().filter(|mv| !reference.contains(mv)).collect();
+            if !missing.is_empty() || !extra.is_empty() {
+                return Err(FuzzMismatch {
+                    fen: board.fen(false),
+                    missing: missing.iter().map(|mv| board.move_to_string(mv)).collect(),
+                    extra: extra.iter().map(|mv| board.move_to_string(mv)).collect(),
+                });
+            }
+            if moves.is_empty() {
+                break;
+            }
+            board.make_move(&moves[splitmix64(&mut rand) as usize % moves.len()]);
+        }
+    }
+    Ok(positions)
+}
+
+fn random_chess960(rand: &mut u64) -> Board {
+    let mut files: Vec<_> = (0..8).map(File::from_u8).collect();
+    let mut take = |rand: &mut u64, parity: Option<u8>| {
+        let candidates: Vec<_> = (0..files.len()).filter(|i| parity.is_none_or(|p| files[*i] as u8 % 2 == p)).collect();
+        files.remove(candidates[splitmix64(rand) as usize % candidates.len()])
+    };
+    let mut back_rank = [Piece::Rook; 8];
+    back_rank[take(rand, Some(0)) as usize] = Piece::Bishop;
+    back_rank[take(rand, Some(1)) as usize] = Piece::Bishop;
+    for piece in [Piece::Queen, Piece::Knight, Piece::Knight] {
+        back_rank[take(rand, None) as usize] = piece;
+    }
+    // The three files left are rook, king and rook from left to right
+    let [queen_side, king_file, king_side] = [files[0], files[1], files[2]];
+    back_rank[king_file as usize] = Piece::King;
+
+    let castling = Castling { king_file, king_side: Some(king_side), queen_side: Some(queen_side) };
+    let mut builder = BoardBuilder::new().castling(Color::White, castling).castling(Color::Black, castling);
+    for (file, piece) in back_rank.iter().enumerate() {
+        let file = File::from_u8(file as u8);
+        for color in Color::ALL {
+            builder = builder
+                .place(Square::from_rank_file(Rank::First.pov(&color), file), color, *piece)
+                .place(Square::from_rank_file(Rank::Second.pov(&color), file), color, Piece::Pawn);
+        }
+    }
+    builder.build().expect("chess960 start positions are valid")
+}
+
+/// A handful of random pieces and the kings on random squares, retried until the position is valid
+fn random_position(rand: &mut u64) -> Board {
+    loop {
+        let mut builder = BoardBuilder::new();
+        let random_sq = |rand: &mut u64| Square::from_u8((splitmix64(rand) % 64) as u8);
+        for _ in 0..splitmix64(rand) % 12 {
+            let color = Color::ALL[(splitmix64(rand) % 2) as usize];
+            let piece = Piece::ALL[(splitmix64(rand) % 5) as usize];
+            builder = builder.place(random_sq(rand), color, piece);
+        }
+        let active_color = Color::ALL[(splitmix64(rand) % 2) as usize];
+        builder = builder
+            .place(random_sq(rand), Color::White, Piece::King)
+            .place(random_sq(rand), Color::Black, Piece::King)
+            .active_color(active_color);
+        if let Ok(board) = builder.build() {
+            return board;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::Board,
+        reference::{fuzz, FUZZ_SEED_POSITIONS},
+    };
+
+    #[test]
+    fn reference_perft() {
+        for fen in FUZZ_SEED_POSITIONS {
+            let mut board = Board::from_fen(fen).expect("fen is valid");
+            assert_eq!(board.reference_perft(2), board.perft(2), "{fen}");
+        }
+        let board = Board::from_fen(FUZZ_SEED_POSITIONS[1]).expect("fen is valid");
+        assert_eq!(board.reference_perft(3), 97862);
+    }
+
+    #[test]
+    fn differential() {
+        if let Err(mismatch) = fuzz(0x5EED, 60, 60) {
+            panic!("{mismatch:?}");
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use util::{bitboard::Bitboard, color::Color, piece::Piece, square::Square};
+
+use crate::{
+    board::Board,
+    movegen::{bishop_moves, rook_moves},
+    mv::{Move, MoveFlag},
+};
+
+/// Piece values used by static exchange evaluation, indexed by `Piece as usize`
+pub const SEE_VALUES: [i32; 6] = [100, 300, 300, 500, 900, 20000];
+
+fn value(piece: &Piece) -> i32 {
+    SEE_VALUES[*piece as usize]
+}
+
+/// The pieces still able to capture on `to` while an exchange is played out
+struct Exchange<'a> {
+    board: &'a Board,
+    to: Square,
+    occupancy: Bitboard,
+    attackers: Bitboard,
+}
+
+impl<'a> Exchange<'a> {
+    fn new(board: &'a Board, mv: &Move) -> Self {
+        let color = &board.state.active_color;
+        let mut occupancy = (board.pieces[color].all | board.pieces[!color].all) ^ mv.from.bitboard();
+        if mv.flag == MoveFlag::EnPassant {
+            occupancy ^= Square::ep_pawn_sq(!color, mv.to.file()).bitboard();
+        }
+        Self {
+            board,
+            to: mv.to,
+            occupancy,
+            attackers: board.attackers_to(&mv.to, occupancy) & occupancy,
+        }
+    }
+
+    fn has_attackers(&self, color: &Color) -> bool {
+        !(self.attackers & self.board.pieces[color].all).is_empty()
+    }
+
+    /// Removes the least valuable attacker of `color` and adds the x-ray attackers standing behind it
+    fn pop_least_valuable(&mut self, color: &Color) -> Option<Piece> {
+        let pieces = &self.board.pieces[color];
+        let (piece, from) = Piece::ALL
+            .iter()
+            .find_map(|p| (self.attackers & pieces[p]).next_sq().map(|sq| (*p, sq)))?;
+
+        self.occupancy ^= from.bitboard();
+        let (white, black) = (&self.board.pieces[&Color::White], &self.board.pieces[&Color::Black]);
+        let diagonal = white.bishop | white.queen | black.bishop | black.queen;
+        let straight = white.rook | white.queen | black.rook | black.queen;
+        self.attackers |= (bishop_moves(&self.to, self.occupancy) & diagonal)
+            | (rook_moves(&self.to, self.occupancy) & straight);
+        self.attackers &= self.occupancy;
+        Some(piece)
+    }
+}
+
+impl Board {
+    /// Material won by the side to move when both sides keep recapturing on the target square of `mv`
+    /// with their least valuable attacker, each side being free to stop when that would lose material.
+    /// Pins are ignored.
+    pub fn see(&self, mv: &Move) -> i32 {
+        let mut exchange = Exchange::new(self, mv);
+        let mut gains = [0; 32];
+        gains[0] = Self::see_captured_value(mv);
+        let mut victim = value(&mv.promotion.map_or(mv.piece, |p| p.piece()));
+        let mut color = !self.state.active_color;
+        let mut depth = 0;
+
+        while let Some(piece) = exchange.pop_least_valuable(&color) {
+            // The king may not capture into a square that is still defended
+            if piece == Piece::King && exchange.has_attackers(&!color) {
+                break;
+            }
+            depth += 1;
+            gains[depth] = victim - gains[depth - 1];
+            victim = value(&piece);
+            color = !color;
+        }
+
+        while depth > 0 {
+            gains[depth - 1] = -(-gains[depth - 1]).max(gains[depth]);
+            depth -= 1;
+        }
+        gains[0]
+    }
+
+    /// Whether `see(mv) >= threshold`, returning as soon as the outcome is decided
+    pub fn see_ge(&self, mv: &Move, threshold: i32) -> bool {
+        // What the side to move is still ahead of the threshold, from the pov of the side about to capture
+        let mut swap = Self::see_captured_value(mv) - threshold;
+        if swap < 0 {
+            return false;
+        }
+        swap = value(&mv.promotion.map_or(mv.piece, |p| p.piece())) - swap;
+        if swap <= 0 {
+            return true;
+        }
+
+        let mut exchange = Exchange::new(self, mv);
+        let mut color = !self.state.active_color;
+        let mut result = true;
+        while let Some(piece) = exchange.pop_least_valuable(&color) {
+            result = !result;
+            if piece == Piece::King {
+                // Only legal if the other side has no attackers left
+                return result != exchange.has_attackers(&!color);
+            }
+            swap = value(&piece) - swap;
+            if swap < result as i32 {
+                break;
+            }
+            color = !color;
+        }
+        result
+    }
+
+    fn see_captured_value(mv: &Move) -> i32 {
+        let captured = match mv.flag {
+            MoveFlag::Capture(piece) => value(&piece),
+            MoveFlag::EnPassant => value(&Piece::Pawn),
+            _ => 0,
+        };
+        captured + mv.promotion.map_or(0, |p| value(&p.piece()) - value(&Piece::Pawn))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn see(fen: &str, mv: &str) -> i32 {
+        let mut board = Board::from_fen(fen).expect("fen is valid");
+        let mv = Move::from_str(mv, &mut board).expect("move is legal");
+        let see = board.see(&mv);
+        for threshold in [see - 1, see, see + 1] {
+            assert_eq!(board.see_ge(&mv, threshold), see >= threshold, "{fen} {mv:?} {threshold}");
+        }
+        see
+    }
+
+    #[test]
+    fn exchanges() {
+        // undefended pawn
+        assert_eq!(see("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"), 100);
+        // knight takes a pawn defended by a pawn
+        assert_eq!(see("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "d3e5"), -200);
+        // quiet move to an attacked square
+        assert_eq!(see("4k3/8/8/4p3/8/8/8/3RK3 w - - 0 1", "d1d4"), -500);
+        // en passant
+        assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+        // promotion capturing a rook defended by a knight
+        assert_eq!(see("r3k3/1P6/1n6/8/8/8/8/4K3 w - - 0 1", "b7a8q"), 400);
+        // the king can only recapture an undefended piece
+        assert_eq!(see("4k3/5p2/8/8/8/8/8/4KR2 w - - 0 1", "f1f7"), -400);
+        assert_eq!(see("4k3/5p2/8/8/8/1B6/8/4KR2 w - - 0 1", "f1f7"), 100);
+    }
+
+    #[test]
+    fn x_rays() {
+        // rook battery against a rook defended once: RxR RxR RxR
+        assert_eq!(see("3r2k1/3r4/8/8/8/3R4/3R4/4K3 w - - 0 1", "d3d7"), 500);
+        // queen behind the bishop joins the attack on the defended pawn
+        assert_eq!(see("4k3/8/2p5/3p4/8/5B2/6Q1/4K3 w - - 0 1", "f3d5"), -100);
+        // without the queen the bishop is lost for a pawn
+        assert_eq!(see("4k3/8/2p5/3p4/8/5B2/8/4K3 w - - 0 1", "f3d5"), -200);
+    }
+
+    #[test]
+    fn see_ge_agrees_with_see() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ] {
+            let mut board = Board::from_fen(fen).expect("fen is valid");
+            for mv in &board.get_moves() {
+                let see = board.see(mv);
+                for threshold in [see - 1, see, see + 1] {
+                    assert_eq!(board.see_ge(mv, threshold), see >= threshold, "{fen} {mv:?} {threshold}");
+                }
+            }
+        }
+    }
+}
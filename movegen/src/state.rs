@@ -2,17 +2,52 @@ use util::{color::Color, square::File};
 
 use crate::both_colors::BothColors;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct State {
     pub active_color: Color,
     pub castling: BothColors<Castling>,
     pub ep_file: Option<File>,
     pub half_move_count: u16,
     pub full_move_count: u16,
+    /// Only changes how castling moves are written, castling itself works the same for any start files.
+    /// When set castling is written as the king capturing its own rook, eg. `e1h1` instead of `e1g1`.
+    pub chess960: bool,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+/// Castling rights of one color.
+/// The files are the start files of the king and the castling rooks, which are only E, A and H in standard chess.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Castling {
-    pub king_side: bool,
-    pub queen_side: bool,
+    pub king_file: File,
+    pub king_side: Option<File>,
+    pub queen_side: Option<File>,
+}
+
+impl Default for Castling {
+    fn default() -> Self {
+        Self {
+            king_file: File::E,
+            king_side: None,
+            queen_side: None,
+        }
+    }
+}
+
+impl Castling {
+    /// Both castling rights of standard chess
+    pub const STANDARD: Self = Self {
+        king_file: File::E,
+        king_side: Some(File::H),
+        queen_side: Some(File::A),
+    };
+
+    /// Removes the castling right that uses the rook starting on `file`, if there is one
+    pub fn remove_rook(&mut self, file: File) {
+        if self.king_side == Some(file) {
+            self.king_side = None;
+        }
+        if self.queen_side == Some(file) {
+            self.queen_side = None;
+        }
+    }
 }
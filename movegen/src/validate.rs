@@ -0,0 +1,118 @@
+use util::{
+    color::Color,
+    error::ChessError,
+    square::{Rank, Square},
+};
+
+use crate::board::Board;
+
+impl Board {
+    /// Checks that the position could come up in a game, so that move generation can rely on it.
+    /// Returns the first problem found.
+    pub fn validate(&self) -> Result<(), ChessError> {
+        for color in &Color::ALL {
+            match self.pieces[color].king.sq_count() {
+                0 => return Err(ChessError::MissingKing(*color)),
+                1 => {}
+                _ => return Err(ChessError::TooManyKings(*color)),
+            }
+        }
+
+        let pawns = self.pieces[&Color::White].pawn | self.pieces[&Color::Black].pawn;
+        if let Some(sq) = (pawns & (Rank::First.bitboard() | Rank::Eighth.bitboard())).next_sq() {
+            return Err(ChessError::PawnOnBackRank(sq));
+        }
+
+        let active_color = &self.state.active_color;
+        let opp_color = !active_color;
+        let occupancy = self.pieces[active_color].all | self.pieces[opp_color].all;
+        let checkers = |color: &Color| {
+            let mut king = self.pieces[color].king;
+            let king_sq = king.next_sq().expect("kings were checked");
+            self.attackers_to(&king_sq, occupancy) & self.pieces[!color].all
+        };
+        if !checkers(opp_color).is_empty() {
+            return Err(ChessError::OpponentInCheck);
+        }
+        if checkers(active_color).sq_count() > 2 {
+            return Err(ChessError::TooManyCheckers);
+        }
+
+        if let Some(ep_file) = self.state.ep_file {
+            // The opponent's pawn has just moved from `from` over `to` to `pawn_sq`
+            let to = Square::ep_move_sq(opp_color, ep_file);
+            let from = Square::from_rank_file(Rank::Second.pov(opp_color), ep_file);
+            let pawn_sq = Square::ep_pawn_sq(opp_color, ep_file);
+            if occupancy.has_sq(to) || occupancy.has_sq(from) || !self.pieces[opp_color].pawn.has_sq(pawn_sq) {
+                return Err(ChessError::InvalidEnPassant(to));
+            }
+        }
+
+        for color in &Color::ALL {
+            let castling = &self.state.castling[color];
+            let rank = Rank::First.pov(color);
+            let rooks_present = [castling.king_side, castling.queen_side]
+                .iter()
+                .flatten()
+                .all(|file| self.pieces[color].rook.has_sq(Square::from_rank_file(rank, *file)));
+            let has_rights = castling.king_side.is_some() || castling.queen_side.is_some();
+            let king_present = self.pieces[color].king.has_sq(Square::from_rank_file(rank, castling.king_file));
+            if !rooks_present || (has_rights && !king_present) {
+                return Err(ChessError::InvalidCastling(*color));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use util::{color::Color, error::ChessError, square::Square};
+
+    use crate::board::Board;
+
+    #[test]
+    fn validate() {
+        for (fen, expected) in [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", Ok(())),
+            ("rnbqkbnr/pppp1ppp/8/8/3pP3/8/PPP2PPP/RNBQKBNR b KQkq e3 0 1", Ok(())),
+            ("8/8/8/8/8/8/8/4K3 w - - 0 1", Err(ChessError::MissingKing(Color::Black))),
+            ("4k3/8/8/8/8/8/8/3KK3 w - - 0 1", Err(ChessError::TooManyKings(Color::White))),
+            ("4k3/8/8/8/8/8/8/P3K3 w - - 0 1", Err(ChessError::PawnOnBackRank(Square::A1))),
+            ("4k3/8/8/8/8/8/8/4KR2 b - - 0 1", Ok(())),
+            ("4k3/8/8/8/8/8/8/4KR2 w - - 0 1", Ok(())),
+            ("4k3/4R3/8/8/8/8/8/4K3 w - - 0 1", Err(ChessError::OpponentInCheck)),
+            ("4k3/8/8/8/1b6/5n2/8/r3K3 w - - 0 1", Err(ChessError::TooManyCheckers)),
+            ("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1", Ok(())),
+            ("4k3/8/8/8/8/8/8/4K3 b - e3 0 1", Err(ChessError::InvalidEnPassant(Square::E3))),
+            ("4k3/8/8/8/4P3/4N3/8/4K3 b - e3 0 1", Err(ChessError::InvalidEnPassant(Square::E3))),
+            ("4k3/8/8/8/8/8/8/4K2R w K - 0 1", Ok(())),
+            ("4k3/8/8/8/8/8/8/4K3 w K - 0 1", Err(ChessError::InvalidCastling(Color::White))),
+            ("r3k3/8/8/8/8/8/8/4K3 w q - 0 1", Ok(())),
+            ("r7/4k3/8/8/8/8/8/4K3 w q - 0 1", Err(ChessError::InvalidCastling(Color::Black))),
+        ] {
+            let board = Board::from_fen(fen).expect("fen parses");
+            assert_eq!(board.validate(), expected, "{fen}");
+            if expected.is_ok() {
+                assert_eq!(board.fen(false), fen);
+            }
+            assert_eq!(Board::from_fen_strict(fen).err(), expected.err(), "{fen}");
+        }
+        // `from_fen` only keeps the file of an en passant square on the wrong rank
+        let fen = "4k3/8/8/4p3/8/8/8/4K3 w - e3 0 1";
+        assert!(Board::from_fen(fen).is_ok());
+        assert_eq!(Board::from_fen_strict(fen).err(), Some(ChessError::InvalidEnPassant(Square::E3)));
+    }
+
+    #[test]
+    fn knight_and_pawn_checks() {
+        // The rook can only capture the checker
+        let mut board = Board::from_fen("k7/8/8/8/8/8/R4n2/7K w - - 0 1").expect("fen is valid");
+        assert!(board.in_check());
+        assert_eq!(board.get_moves().len(), 4);
+        let mut board = Board::from_fen("k7/8/8/8/8/8/R5p1/7K w - - 0 1").expect("fen is valid");
+        assert!(board.in_check());
+        assert_eq!(board.get_moves().len(), 4);
+    }
+}
@@ -0,0 +1,89 @@
+use util::{color::Color, piece::Piece, square::{File, Square}};
+
+use crate::{both_colors::BothColors, state::Castling};
+
+pub struct ZobristKeys {
+    pieces: [[[u64; 64]; 6]; 2],
+    castling: [[u64; 2]; 2],
+    ep_file: [u64; 8],
+    black_to_move: u64,
+}
+
+/// see https://prng.di.unimi.it/splitmix64.c
+pub(crate) const fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+const fn generate_zobrist_keys() -> ZobristKeys {
+    let mut state = 0x2545F4914F6CDD1D;
+    let mut keys = ZobristKeys {
+        pieces: [[[0; 64]; 6]; 2],
+        castling: [[0; 2]; 2],
+        ep_file: [0; 8],
+        black_to_move: 0,
+    };
+
+    let mut c = 0;
+    while c < 2 {
+        let mut p = 0;
+        while p < 6 {
+            let mut sq = 0;
+            while sq < 64 {
+                keys.pieces[c][p][sq] = splitmix64(&mut state);
+                sq += 1;
+            }
+            p += 1;
+        }
+        keys.castling[c][0] = splitmix64(&mut state);
+        keys.castling[c][1] = splitmix64(&mut state);
+        c += 1;
+    }
+
+    let mut f = 0;
+    while f < 8 {
+        keys.ep_file[f] = splitmix64(&mut state);
+        f += 1;
+    }
+
+    keys.black_to_move = splitmix64(&mut state);
+    keys
+}
+
+/// A `static` so every lookup reads the same table instead of a copy inlined at the use site
+static ZOBRIST: ZobristKeys = generate_zobrist_keys();
+
+pub fn piece_key(color: &Color, piece: &Piece, sq: &Square) -> u64 {
+    ZOBRIST.pieces[*color as usize][*piece as usize][sq.idx()]
+}
+
+pub fn castling_key(castling: &BothColors<Castling>) -> u64 {
+    let mut key = 0;
+    for color in &Color::ALL {
+        let c = &castling[color];
+        if c.king_side.is_some() {
+            key ^= ZOBRIST.castling[*color as usize][0];
+        }
+        if c.queen_side.is_some() {
+            key ^= ZOBRIST.castling[*color as usize][1];
+        }
+    }
+    key
+}
+
+pub fn ep_file_key(ep_file: Option<File>) -> u64 {
+    match ep_file {
+        Some(file) => ZOBRIST.ep_file[file as usize],
+        None => 0,
+    }
+}
+
+pub fn active_color_key(color: &Color) -> u64 {
+    match color {
+        Color::White => 0,
+        Color::Black => ZOBRIST.black_to_move,
+    }
+}
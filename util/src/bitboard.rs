@@ -32,6 +32,17 @@ impl Bitboard {
         X . . . . . . X
         X X X X X X X X
     );
+    pub const LIGHT_SQUARES: Self = make_bitboard!(
+        X . X . X . X .
+        . X . X . X . X
+        X . X . X . X .
+        . X . X . X . X
+        X . X . X . X .
+        . X . X . X . X
+        X . X . X . X .
+        . X . X . X . X
+    );
+    pub const DARK_SQUARES: Self = Self(!Self::LIGHT_SQUARES.0);
 
     pub fn sq_count(&self) -> u32 {
         self.0.count_ones()
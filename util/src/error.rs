@@ -1,6 +1,21 @@
-#[derive(Debug)]
+use crate::{color::Color, square::Square};
+
+#[derive(Debug, PartialEq)]
 pub enum ChessError {
     Parse(String),
     InvalidPieceAccess,
     InvalidMove(String),
+    /// The color does not have a king
+    MissingKing(Color),
+    /// The color has more than one king
+    TooManyKings(Color),
+    PawnOnBackRank(Square),
+    /// The side that just moved was left in check
+    OpponentInCheck,
+    /// The side to move is checked by more than two pieces
+    TooManyCheckers,
+    /// The en passant target square cannot follow a double pawn push
+    InvalidEnPassant(Square),
+    /// The color has castling rights without its king or rook on their start squares
+    InvalidCastling(Color),
 }
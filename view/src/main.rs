@@ -1,10 +1,11 @@
 use axum::{
     extract::{Path, Query},
+    http::header,
     response::{Html, IntoResponse},
     routing::get,
     Router,
 };
-use movegen::{board::Board, Color, Square};
+use movegen::{board::Board, mv::UciMove, parse::pgn::Game, Color, Square};
 
 use serde::Deserialize;
 use tower_http::services::ServeDir;
@@ -12,6 +13,41 @@ use tower_http::services::ServeDir;
 #[derive(Deserialize)]
 struct BoardPageParams {
     selected: Option<String>,
+    /// Fen the game started from, defaults to the position shown
+    start: Option<String>,
+    /// Moves played since `start`, comma separated
+    moves: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PgnParams {
+    start: String,
+    moves: Option<String>,
+}
+
+/// Replays `moves` from `start` and serves the game as a PGN download
+async fn pgn(Query(params): Query<PgnParams>) -> impl IntoResponse {
+    let mut board = match Board::from_fen_strict(&params.start.replace('_', " ")) {
+        Ok(b) => b,
+        Err(e) => return format!("{:?}", e).into_response(),
+    };
+    let mut game = Game::new(board);
+    for mv in params.moves.iter().flat_map(|moves| moves.split(',')).filter(|mv| !mv.is_empty()) {
+        let mv = match mv.parse::<UciMove>().and_then(|mv| board.resolve(mv)) {
+            Ok(mv) => mv,
+            Err(e) => return format!("{:?}", e).into_response(),
+        };
+        board.make_move(&mv);
+        game.push(mv);
+    }
+    (
+        [
+            (header::CONTENT_TYPE, "application/x-chess-pgn"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"game.pgn\""),
+        ],
+        game.to_pgn(),
+    )
+        .into_response()
 }
 
 async fn board_page(
@@ -19,7 +55,7 @@ async fn board_page(
     Query(params): Query<BoardPageParams>,
 ) -> impl IntoResponse {
     let fen = fen.replace('_', " ");
-    let mut board = match Board::from_fen(&fen) {
+    let mut board = match Board::from_fen_strict(&fen) {
         Ok(b) => b,
         Err(e) => return format!("{:?}", e).into_response(),
     };
@@ -33,6 +69,25 @@ async fn board_page(
         None
     };
 
+    // The links are rebuilt from the parsed game, so nothing from the query string ends up in the page verbatim
+    let mut replay = match params.start.map(|start| Board::from_fen_strict(&start.replace('_', " "))) {
+        Some(Ok(b)) => b,
+        Some(Err(e)) => return format!("{:?}", e).into_response(),
+        None => board,
+    };
+    let start = replay.fen(true);
+    let mut played = Vec::new();
+    for mv in params.moves.iter().flat_map(|moves| moves.split(',')).filter(|mv| !mv.is_empty()) {
+        let mv = match mv.parse::<UciMove>().and_then(|mv| replay.resolve(mv)) {
+            Ok(mv) => mv,
+            Err(e) => return format!("{:?}", e).into_response(),
+        };
+        played.push(replay.move_to_string(&mv));
+        replay.make_move(&mv);
+    }
+    let moves_played = played.join(",");
+    let query = |moves: &str| format!("start={start}&moves={moves}");
+
     let board_html = board
         .all_sqs(true)
         .iter()
@@ -54,12 +109,16 @@ async fn board_page(
                             if let Some(mv) = moves.iter().find(|mv| mv.to == *sq) {
                                 let mut new_board = board;
                                 new_board.make_move(mv);
-                                format!("/{}", new_board.fen(true))
+                                let played = match moves_played.as_str() {
+                                    "" => board.move_to_string(mv),
+                                    played => format!("{played},{}", board.move_to_string(mv)),
+                                };
+                                format!("/{}?{}", new_board.fen(true), query(&played))
                             } else {
-                                format!("/{}", board.fen(true))
+                                format!("/{}?{}", board.fen(true), query(&moves_played))
                             }
                         } else {
-                            format!("/{}?selected={}", board.fen(true), sq.to_string())
+                            format!("/{}?selected={}&{}", board.fen(true), sq.to_string(), query(&moves_played))
                         },
                         if let 0 = (sq.rank() as usize + sq.file() as usize) % 2 {
                             "Coral"
@@ -131,9 +190,11 @@ async fn board_page(
                     <div class=\"board\">
                         {board_html}
                     </div>
+                    <a href=\"/pgn?{}\" download>Save PGN</a>
             </body>
         </html>
-    "
+    ",
+        query(&moves_played)
     ))
     .into_response()
 }
@@ -142,6 +203,7 @@ async fn board_page(
 async fn main() {
     // build our application with a single route
     let app = Router::new()
+        .route("/pgn", get(pgn))
         .route("/*fen", get(board_page))
         .nest_service("/static", ServeDir::new("./static"));
 